@@ -1,7 +1,11 @@
 use crate::backends::BuildProfile;
+use crate::package::lock::LockMode;
+use crate::package::tree::{GitLock, PackageDetails, ProjectSource};
 use clap::{Args, Parser, Subcommand};
 use serde_derive::{Deserialize, Serialize};
 use std::path::PathBuf;
+use url::Url;
+use versions::{Requirement, Versioning};
 
 #[derive(clap::ValueEnum, Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Hash)]
 #[value(rename_all = "lowercase")]
@@ -69,6 +73,10 @@ pub struct BuildArgs {
     #[arg(short, long, value_delimiter = ',')]
     pub apps: Vec<String>,
 
+    /// Workspace members whose apps are built, all members if left empty
+    #[arg(short, long = "package", value_delimiter = ',')]
+    pub packages: Vec<String>,
+
     /// Number of threads to use for parallel builds. Zero means it will be determined automatically.
     #[arg(short, long, default_value_t = 0)]
     pub threads: usize,
@@ -78,6 +86,26 @@ pub struct BuildArgs {
 
     #[arg(short, long)]
     pub visualize: bool,
+
+    /// Fails if the Lingo.lock is missing or would need to change
+    #[arg(long)]
+    pub locked: bool,
+
+    /// Like --locked but also never fetches packages over the network
+    #[arg(long)]
+    pub frozen: bool,
+
+    /// Only uses packages from the cache or the local file system, also set by LINGO_OFFLINE
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Features of the package to enable
+    #[arg(short = 'F', long, value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Doesn't enable the default feature of the package
+    #[arg(long)]
+    pub no_default_features: bool,
 }
 
 impl BuildArgs {
@@ -88,6 +116,16 @@ impl BuildArgs {
             BuildProfile::Debug
         }
     }
+
+    pub fn lock_mode(&self) -> LockMode {
+        if self.frozen {
+            LockMode::Frozen
+        } else if self.locked {
+            LockMode::Locked
+        } else {
+            LockMode::Update
+        }
+    }
 }
 
 #[derive(Args, Debug)]
@@ -112,6 +150,197 @@ impl InitArgs {
     }
 }
 
+#[derive(Args, Debug)]
+pub struct UpdateArgs {
+    /// List of packages to update if left empty all dependencies are updated
+    #[arg(value_delimiter = ',')]
+    pub packages: Vec<String>,
+
+    /// Only uses packages from the cache or the local file system, also set by LINGO_OFFLINE
+    #[arg(long)]
+    pub offline: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[value(rename_all = "lowercase")]
+pub enum TreeFormat {
+    #[default]
+    Text,
+    Dot,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct TreeArgs {
+    /// Shows which packages depend on the given package instead
+    #[arg(short, long)]
+    pub invert: Option<String>,
+
+    /// Output format of the dependency graph
+    #[arg(value_enum, short, long, default_value_t = TreeFormat::Text)]
+    pub format: TreeFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct AddArgs {
+    /// Name of the package
+    pub name: String,
+
+    /// Version requirement of the package, any version if left empty
+    #[arg(long)]
+    pub version: Option<Requirement>,
+
+    /// Git repository the package is cloned from
+    #[arg(long, group = "source")]
+    pub git: Option<Url>,
+
+    /// Local directory that contains the package
+    #[arg(long, group = "source")]
+    pub path: Option<PathBuf>,
+
+    /// Archive the package is downloaded from
+    #[arg(long, group = "source")]
+    pub tarball: Option<Url>,
+
+    /// Registry index the package is looked up in instead of the default registry
+    #[arg(long, group = "source")]
+    pub registry: Option<Url>,
+
+    /// Git tag that is checked out
+    #[arg(long, requires = "git", group = "reference")]
+    pub tag: Option<String>,
+
+    /// Git branch that is checked out
+    #[arg(long, requires = "git", group = "reference")]
+    pub branch: Option<String>,
+
+    /// Git revision that is checked out
+    #[arg(long, requires = "git", group = "reference")]
+    pub rev: Option<String>,
+
+    /// Directory inside the git repository or archive that contains the package
+    #[arg(long)]
+    pub subdir: Option<PathBuf>,
+
+    /// Doesn't check out the submodules of the git repository
+    #[arg(long, requires = "git")]
+    pub no_submodules: bool,
+
+    /// Only uses packages from the cache or the local file system, also set by LINGO_OFFLINE
+    #[arg(long)]
+    pub offline: bool,
+}
+
+impl AddArgs {
+    /// dependency entry for the Lingo.toml
+    pub fn package_details(&self) -> PackageDetails {
+        let mutual_exclusive = match (&self.git, &self.path, &self.tarball) {
+            (Some(git), _, _) => ProjectSource::Git(git.clone()),
+            (_, Some(path), _) => ProjectSource::Path(path.clone()),
+            (_, _, Some(tarball)) => ProjectSource::TarBall(tarball.clone()),
+            _ => ProjectSource::Registry(self.registry.clone()),
+        };
+
+        let git_tag = match (&self.tag, &self.branch, &self.rev) {
+            (Some(tag), _, _) => Some(GitLock::Tag(tag.clone())),
+            (_, Some(branch), _) => Some(GitLock::Branch(branch.clone())),
+            (_, _, Some(rev)) => Some(GitLock::Rev(rev.clone())),
+            _ => None,
+        };
+
+        PackageDetails {
+            version: self.version.clone().unwrap_or_default(),
+            mutual_exclusive,
+            git_tag,
+            subdir: self.subdir.clone(),
+            submodules: !self.no_submodules,
+            features: Default::default(),
+            git_rev: None,
+            archive_checksum: None,
+            locked_version: None,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct RemoveArgs {
+    /// Names of the packages that are removed
+    #[arg(required = true, value_delimiter = ',')]
+    pub packages: Vec<String>,
+
+    /// Only uses packages from the cache or the local file system, also set by LINGO_OFFLINE
+    #[arg(long)]
+    pub offline: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VendorArgs {
+    /// Directory inside the package the dependencies are copied to
+    #[arg(default_value = "vendor")]
+    pub directory: PathBuf,
+
+    /// Only uses packages from the cache or the local file system, also set by LINGO_OFFLINE
+    #[arg(long)]
+    pub offline: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PackageArgs {
+    /// Skips unpacking the archive and resolving its dependencies after it was written
+    #[arg(long)]
+    pub no_verify: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PublishArgs {
+    /// Registry index the package is published to, either a directory or a git url.
+    /// Defaults to the registry of the Lingo.toml or LINGO_REGISTRY
+    #[arg(long)]
+    pub registry: Option<String>,
+
+    /// Location the archive is uploaded to, required for git registries
+    #[arg(long)]
+    pub archive_url: Option<Url>,
+
+    /// Publishes even if the package has uncommitted changes
+    #[arg(long)]
+    pub allow_dirty: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct YankArgs {
+    /// Published version of the package that is yanked
+    pub version: Versioning,
+
+    /// Registry index the version was published to, either a directory or a git url.
+    /// Defaults to the registry of the Lingo.toml or LINGO_REGISTRY
+    #[arg(long)]
+    pub registry: Option<String>,
+
+    /// Makes a yanked version available again
+    #[arg(long)]
+    pub undo: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct PruneArgs {
+    /// Removes packages that haven't been used for this many days, zero removes all of them
+    #[arg(long, default_value_t = 30)]
+    pub older_than: u64,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// lists all packages in the cache
+    List,
+
+    /// removes packages that haven't been used recently
+    Prune(PruneArgs),
+
+    /// checks the integrity of all cached packages and removes broken ones
+    Verify,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// initializing a lingua-franca project
@@ -121,13 +350,44 @@ pub enum Command {
     Build(BuildArgs),
 
     /// Updates the dependencies and potentially build tools
-    Update,
+    Update(UpdateArgs),
+
+    /// adds a dependency to the Lingo.toml and updates the Lingo.lock
+    Add(Box<AddArgs>),
+
+    /// removes dependencies from the Lingo.toml and updates the Lingo.lock
+    Remove(RemoveArgs),
 
     /// builds and runs binaries
     Run(BuildArgs),
 
+    /// builds all apps including the examples with the dev-dependencies and runs them
+    Test(BuildArgs),
+
     /// removes build artifacts
     Clean,
+
+    /// lists dependencies for which newer versions are available
+    Outdated,
+
+    /// prints the dependency graph recorded in the Lingo.lock
+    Tree(TreeArgs),
+
+    /// copies all dependencies into the package so they don't have to be fetched anymore
+    Vendor(VendorArgs),
+
+    /// packs the library into an archive that can be used as tarball dependency
+    Package(PackageArgs),
+
+    /// packs the library and adds the new version to a registry index
+    Publish(PublishArgs),
+
+    /// marks a published version so it isn't selected for new dependencies anymore
+    Yank(YankArgs),
+
+    /// manages the package cache that is shared between projects
+    #[clap(subcommand)]
+    Cache(CacheCommand),
 }
 
 #[derive(Parser, Debug)]
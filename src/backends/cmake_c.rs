@@ -112,7 +112,6 @@ impl BatchBackend for CmakeC {
                     Ok(())
                 });
             }
-            _ => todo!(),
         }
     }
 }
@@ -52,7 +52,6 @@ impl BatchBackend for LFC {
             CommandSpec::Build(options) => {
                 LFC::do_parallel_lfc_codegen(options, results, options.compile_target_code)
             }
-            CommandSpec::Update => todo!(),
             CommandSpec::Clean => {
                 results.par_map(|app| {
                     crate::util::default_build_clean(&app.output_root)?;
@@ -8,11 +8,12 @@ use std::fs;
 
 use crate::args::{BuildSystem, Platform, TargetLanguage};
 use crate::package::{
-    management::DependencyManager, target_properties::MergeTargetProperties, App, Config,
-    OUTPUT_DIRECTORY,
+    lock::LockMode, management::DependencyManager, target_properties::MergeTargetProperties, App,
+    Config, OUTPUT_DIRECTORY,
 };
 use crate::util::errors::{AnyError, BuildResult, LingoError};
-use crate::{GitCloneAndCheckoutCap, WhichCapability};
+
+use crate::{DownloadCap, GitCloneAndCheckoutCap, WhichCapability};
 
 pub mod cmake_c;
 pub mod cmake_cpp;
@@ -20,65 +21,57 @@ pub mod lfc;
 pub mod npm;
 pub mod pnpm;
 
-#[allow(clippy::single_match)] // there more options will be added to this match block
+/// Runs the command for all apps of the `config`. Errors that happen before the apps are
+/// handed to their backend, like failing to load the dependencies, fail the whole command.
 pub fn execute_command<'a>(
     command: &CommandSpec,
     config: &'a mut Config,
     which: WhichCapability,
     clone: GitCloneAndCheckoutCap,
-) -> BatchBuildResults<'a> {
+    download: DownloadCap,
+) -> Result<BatchBuildResults<'a>, Box<AnyError>> {
     let mut result = BatchBuildResults::new();
-    let dependencies = Vec::from_iter(config.dependencies.clone());
+    let root = config.root.clone();
+    let output = root.join(OUTPUT_DIRECTORY);
 
     // log::info!("Building command running config:{:?}", config);
 
     match command {
-        CommandSpec::Build(_build) => {
-            let manager = match DependencyManager::from_dependencies(
-                dependencies.clone(),
-                &PathBuf::from(OUTPUT_DIRECTORY),
+        CommandSpec::Build(build) => {
+            let packages = config.build_dependency_names(build.dev);
+            let manager = DependencyManager::from_dependencies(
+                config,
+                &packages,
+                &output,
+                build.lock_mode,
+                build.offline,
                 &clone,
-            ) {
-                Ok(value) => value,
-                Err(e) => {
-                    error!("failed to create dependency manager because of {e}");
-                    return result;
-                }
-            };
+                &download,
+            )?;
 
-            // enriching the apps with the target properties from the libraries
-            let library_properties = manager.get_target_properties().expect("lib properties");
-
-            // log::info!("library_properties:{:?}", library_properties);
+            // every app is enriched with the target properties of its own libraries
+            let app_dependencies = config
+                .apps
+                .iter()
+                .map(|app| config.app_dependency_names(app, build.dev))
+                .collect::<Vec<_>>();
 
             // merging app with library target properties
-            for app in &mut config.apps {
-                if let Err(e) = app.properties.merge(&library_properties) {
-                    error!("cannot merge properties from the libraries with the app. error: {e}");
-                    return result;
-                }
+            for (app, dependencies) in config.apps.iter_mut().zip(app_dependencies) {
+                let library_properties = manager.get_target_properties(&dependencies)?;
+                app.properties.merge(&library_properties)?;
             }
         }
         CommandSpec::Clean => {
             log::info!("TODO!!!");
-            let build_dir = &PathBuf::from(OUTPUT_DIRECTORY);
-            if build_dir.exists() {
-                fs::remove_dir_all(build_dir).expect("Failed to remove build directory");
+            if output.exists() {
+                fs::remove_dir_all(&output).expect("Failed to remove build directory");
                 log::info!("Build folder removed");
             }
 
-            let cleanup_result = match DependencyManager::cleanup(&PathBuf::from(".")
-            ) {
-                Ok(value) => value,
-                Err(e) => {
-                    error!("failed to create dependency manager because of {e}");
-                    return result;
-                }
-            };
-
-            return result;
+            DependencyManager::cleanup(&root)?;
+            return Ok(result);
         }
-        _ => {}
     }
 
     // Group apps by build system
@@ -126,7 +119,7 @@ pub fn execute_command<'a>(
         };
         result.append(sub_res);
     }
-    result
+    Ok(result)
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -151,14 +144,18 @@ pub struct BuildCommandOptions {
     pub max_threads: usize,
     /// if compilation should continue if one of the apps fails building
     pub keep_going: bool,
+    /// whether the Lingo.lock may be changed and packages may be fetched
+    pub lock_mode: LockMode,
+    /// if packages may only come from the cache or the local file system
+    pub offline: bool,
+    /// if all apps are built with the dev-dependencies, which `lingo test` does
+    pub dev: bool,
 }
 
 /// Description of a lingo command
 pub enum CommandSpec {
     /// Compile generated code with the target compiler.
     Build(BuildCommandOptions),
-    /// Update dependencies
-    Update,
     /// Clean build artifacts
     Clean,
 }
@@ -197,6 +194,11 @@ impl<'a> BatchBuildResults<'a> {
         self.keep_going = value
     }
 
+    /// if the command failed for any of the apps
+    pub fn has_failures(&self) -> bool {
+        self.results.iter().any(|(_, result)| result.is_err())
+    }
+
     /// Print this result collection to standard output.
     pub fn print_results(&self) {
         for (app, b) in &self.results {
@@ -132,7 +132,6 @@ impl BatchBackend for Npm {
                     Ok(())
                 });
             }
-            _ => todo!(),
         }
     }
 }
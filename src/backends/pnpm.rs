@@ -23,7 +23,6 @@ impl BatchBackend for Pnpm {
                     Ok(())
                 });
             }
-            _ => todo!(),
         }
     }
 }
@@ -17,6 +17,8 @@ pub enum WhichError {
 }
 #[derive(Debug)]
 pub struct GitCloneError(pub String); // TODO: create a more domain-specific error time like the actual git2::Error
+#[derive(Debug)]
+pub struct DownloadError(pub String);
 
 impl std::fmt::Display for WhichError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -36,10 +38,38 @@ impl std::fmt::Display for GitCloneError {
     }
 }
 
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl std::error::Error for WhichError {}
 
 impl std::error::Error for GitCloneError {}
 
+impl std::error::Error for DownloadError {}
+
+/// How a repository is cloned by the [`GitCloneAndCheckoutCap`]
+#[derive(Clone, Copy, Debug)]
+pub struct GitCloneOptions {
+    /// if the submodules are checked out as well
+    pub submodules: bool,
+    /// Only the files of the revision are needed. They are checked out from a bare mirror in
+    /// the package cache that is shared between projects, the destination doesn't become a
+    /// git repository.
+    pub files_only: bool,
+}
+
+impl Default for GitCloneOptions {
+    fn default() -> Self {
+        GitCloneOptions {
+            submodules: true,
+            files_only: false,
+        }
+    }
+}
+
 pub struct GitUrl<'a>(&'a str);
 
 impl<'a> From<&'a str> for GitUrl<'a> {
@@ -59,5 +89,14 @@ pub type GitCloneCapability<'a> =
     Box<dyn Fn(GitUrl, &std::path::Path) -> Result<(), GitCloneError> + 'a>;
 pub type FsReadCapability<'a> = Box<dyn Fn(&std::path::Path) -> io::Result<String> + 'a>;
 pub type GitCloneAndCheckoutCap<'a> = Box<
-    dyn Fn(GitUrl, &std::path::Path, Option<GitLock>) -> Result<Option<String>, GitCloneError> + 'a,
+    dyn Fn(
+            GitUrl,
+            &std::path::Path,
+            Option<GitLock>,
+            GitCloneOptions,
+        ) -> Result<Option<String>, GitCloneError>
+        + 'a,
 >;
+pub type GitListTagsCap<'a> = Box<dyn Fn(GitUrl) -> Result<Vec<String>, GitCloneError> + 'a>;
+pub type DownloadCap<'a> =
+    Box<dyn Fn(&url::Url, &std::path::Path) -> Result<(), DownloadError> + 'a>;
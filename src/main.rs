@@ -2,20 +2,31 @@ use liblingo::args::TargetLanguage;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, SystemTime};
 use std::{env, io};
 
 use clap::Parser;
-use git2::BranchType::{Local, Remote};
-use git2::{BranchType, Object, ObjectType, Reference, Repository};
 use liblingo::args::InitArgs;
-use liblingo::args::{BuildArgs, Command as ConsoleCommand, CommandLineArgs};
+use liblingo::args::{
+    BuildArgs, CacheCommand, Command as ConsoleCommand, CommandLineArgs, PackageArgs, PublishArgs,
+    TreeArgs, VendorArgs, YankArgs,
+};
 use liblingo::backends::{BatchBuildResults, BuildCommandOptions, CommandSpec};
-use liblingo::package::tree::GitLock;
-use liblingo::package::{Config, ConfigFile};
-use liblingo::util::errors::{BuildResult, LingoError};
-use liblingo::{GitCloneAndCheckoutCap, GitCloneError, GitUrl, WhichCapability, WhichError};
+use liblingo::package::cache::PackageCache;
+use liblingo::package::graph::DependencyGraph;
+use liblingo::package::management::{offline_from_env, DependencyManager};
+use liblingo::package::workspace::{self, WorkspaceConfigFile};
+use liblingo::package::{edit, outdated, packaging, publish};
+use liblingo::package::{App, Config, ConfigFile, OUTPUT_DIRECTORY};
+use liblingo::util::errors::{AnyError, BuildResult, LingoError};
+use liblingo::util::git;
+use liblingo::{
+    DownloadCap, DownloadError, GitCloneAndCheckoutCap, GitListTagsCap, WhichCapability, WhichError,
+};
 use log::LevelFilter;
 
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
 fn do_which(cmd: &str) -> Result<PathBuf, WhichError> {
     which::which(cmd).map_err(|err| match err {
         which::Error::CannotFindBinaryPath => WhichError::CannotFindBinaryPath,
@@ -26,66 +37,18 @@ fn do_which(cmd: &str) -> Result<PathBuf, WhichError> {
     })
 }
 
-fn get_branch<'a>(
-    repo: &'a git2::Repository,
-    branch: &str,
-    branch_type: BranchType,
-) -> Result<(Object<'a>, Option<Reference<'a>>), GitCloneError> {
-    let reference = repo
-        .find_branch(branch, branch_type)
-        .map_err(|_| GitCloneError("cannot find branch".to_string()))?
-        .into_reference();
-    Ok((
-        reference
-            .peel(ObjectType::Any)
-            .map_err(|_| GitCloneError("cannot peel object".to_string()))?,
-        Some(reference),
-    ))
-}
+fn do_download(url: &url::Url, outpath: &Path) -> Result<(), DownloadError> {
+    let response = ureq::get(url.as_str())
+        .call()
+        .map_err(|e| DownloadError(format!("cannot download {url}: {e}")))?;
 
-fn do_clone_and_checkout(
-    git_url: GitUrl,
-    outpath: &Path,
-    git_tag: Option<GitLock>,
-) -> Result<Option<String>, GitCloneError> {
-    let repo = Repository::clone_recurse(<&str>::from(git_url), outpath)
-        .map_err(|_| GitCloneError("clone failed".to_string()))?;
-    let mut git_rev = None;
-
-    if let Some(git_lock) = git_tag {
-        let (object, reference) = match git_lock {
-            GitLock::Tag(tag) => repo
-                .revparse_ext(&tag)
-                .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
-            GitLock::Branch(branch) => {
-                if let Ok(val) = get_branch(&repo, &branch, Local) {
-                    val
-                } else {
-                    get_branch(&repo, &branch, Remote)?
-                }
-            }
-            GitLock::Rev(rev) => repo
-                .revparse_ext(&rev)
-                .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
-        };
-        repo.checkout_tree(&object, None)
-            .map_err(|e| GitCloneError(format!("cannot checkout rev {e}")))?;
-
-        // TODO: this produces hard to debug output
-
-        match reference {
-            // gref is an actual reference like branches or tags
-            Some(gref) => {
-                git_rev = gref.target().map(|v| v.to_string());
-                repo.set_head(gref.name().unwrap())
-            }
-            // this is a commit, not a reference
-            None => repo.set_head_detached(object.id()),
-        }
-        .map_err(|_| GitCloneError("cannot checkout rev".to_string()))?;
-    }
+    let mut file = std::fs::File::create(outpath)
+        .map_err(|e| DownloadError(format!("cannot create {}: {e}", outpath.display())))?;
+
+    io::copy(&mut response.into_reader(), &mut file)
+        .map_err(|e| DownloadError(format!("cannot download {url}: {e}")))?;
 
-    Ok(git_rev)
+    Ok(())
 }
 
 fn do_read_to_string(p: &Path) -> io::Result<String> {
@@ -103,13 +66,35 @@ fn main() {
     let lingo_path = liblingo::util::find_toml(&env::current_dir().unwrap());
     // log::info!("lingo toml file: {:?}", lingo_path);
 
+    // dependencies are edited before Lingo.toml is read so the lock is updated for the new file
+    let original_toml = match lingo_path
+        .as_deref()
+        .map(|path| edit_dependencies(path, &args.command))
+    {
+        Some(Ok(original)) => original,
+        Some(Err(e)) => {
+            print_res(Err(e));
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    // commands that resolve or build dependencies work on the whole workspace
+    let workspace_path = lingo_path
+        .as_deref()
+        .filter(|_| uses_workspace(&args.command))
+        .and_then(workspace::find_workspace);
+
     // tries to read Lingo.toml
-    let mut wrapped_config: Option<Config> = lingo_path.as_ref().and_then(|path: &PathBuf| {
-        ConfigFile::from(path, Box::new(do_read_to_string))
-            .map_err(|err| log::error!("Error while reading Lingo.toml: {}", err))
-            .ok()
-            .map(|cf| cf.to_config(path.parent().unwrap()))
-    });
+    let mut wrapped_config: Option<Config> = match &workspace_path {
+        Some(path) => read_workspace(path),
+        None => lingo_path.as_ref().and_then(|path: &PathBuf| {
+            ConfigFile::from(path, Box::new(do_read_to_string))
+                .map_err(|err| log::error!("Error while reading Lingo.toml: {}", err))
+                .ok()
+                .map(|cf| cf.to_config(path.parent().unwrap()))
+        }),
+    };
 
     // log::info!("Toml config file:{:?}", wrapped_config);
 
@@ -124,15 +109,60 @@ fn main() {
         &mut wrapped_config,
         args.command,
         Box::new(do_which),
-        Box::new(do_clone_and_checkout),
+        Box::new(git::clone_and_checkout),
     );
 
-    match result {
-        CommandResult::Batch(res) => res.print_results(),
-        CommandResult::Single(res) => print_res(res),
+    let failed = match result {
+        CommandResult::Batch(Ok(res)) => {
+            res.print_results();
+            res.has_failures()
+        }
+        CommandResult::Batch(Err(e)) => {
+            log::error!("{}", e);
+            true
+        }
+        CommandResult::Single(res) => {
+            let failed = res.is_err();
+            print_res(res);
+            failed
+        }
+    };
+
+    // scripts and CI rely on the exit code to notice failed builds or outdated locks
+    if failed {
+        // the edit of the Lingo.toml is only kept if the lock could be updated for it
+        if let (Some(path), Some(original)) = (&lingo_path, original_toml) {
+            if let Err(e) = std::fs::write(path, original) {
+                log::error!("cannot restore {}: {e}", path.display());
+            }
+        }
+        std::process::exit(1);
     }
 }
 
+/// packaging and publishing always work on the package itself, even inside a workspace
+fn uses_workspace(command: &ConsoleCommand) -> bool {
+    !matches!(
+        command,
+        ConsoleCommand::Init(_)
+            | ConsoleCommand::Cache(_)
+            | ConsoleCommand::Package(_)
+            | ConsoleCommand::Publish(_)
+            | ConsoleCommand::Yank(_)
+    )
+}
+
+/// Reads the workspace root and all of its members, which share the lock and the output
+/// directory of the root.
+fn read_workspace(path: &Path) -> Option<Config> {
+    let root = path.parent().unwrap();
+    WorkspaceConfigFile::from(path, Box::new(do_read_to_string))
+        .map_err(anyhow::Error::from)
+        .and_then(|workspace| workspace.to_config(root, Box::new(do_read_to_string)))
+        .map_err(|err| log::error!("Error while reading the workspace: {}", err))
+        .ok()
+}
+
 fn print_res(result: BuildResult) {
     match result {
         Ok(_) => {
@@ -144,10 +174,32 @@ fn print_res(result: BuildResult) {
     }
 }
 
+/// Adds or removes dependencies in the Lingo.toml, returns its previous content if it changed
+fn edit_dependencies(
+    lingo_toml: &Path,
+    command: &ConsoleCommand,
+) -> Result<Option<String>, Box<AnyError>> {
+    let original = std::fs::read_to_string(lingo_toml)?;
+    match command {
+        ConsoleCommand::Add(add) => {
+            edit::add_dependency(lingo_toml, &add.name, &add.package_details())?
+        }
+        ConsoleCommand::Remove(remove) => edit::remove_dependencies(lingo_toml, &remove.packages)?,
+        _ => return Ok(None),
+    }
+    Ok(Some(original))
+}
+
 fn validate(config: &mut Option<Config>, command: &ConsoleCommand) -> BuildResult {
     match (config, command) {
         (Some(config), ConsoleCommand::Build(build))
-        | (Some(config), ConsoleCommand::Run(build)) => {
+        | (Some(config), ConsoleCommand::Run(build))
+        | (Some(config), ConsoleCommand::Test(build)) => {
+            if !build.packages.is_empty() {
+                config.select_packages(&build.packages)?;
+            }
+            config.select_features(&build.features, !build.no_default_features)?;
+
             let unknown_names = build
                 .apps
                 .iter()
@@ -160,6 +212,8 @@ fn validate(config: &mut Option<Config>, command: &ConsoleCommand) -> BuildResul
             // Now remove the apps that were not selected by the CLI
             if !build.apps.is_empty() {
                 config.apps.retain(|app| build.apps.contains(&app.name));
+            } else if !matches!(command, ConsoleCommand::Test(_)) {
+                config.apps.retain(|app| !app.example);
             }
             Ok(())
         }
@@ -178,42 +232,213 @@ fn execute_command<'a>(
             // log::info!("Executing Init Command");
             CommandResult::Single(do_init(init_config, &git_clone_capability))
         }
+        (_, ConsoleCommand::Cache(cache_command)) => CommandResult::Single(do_cache(cache_command)),
         (None, _) => CommandResult::Single(Err(Box::new(io::Error::new(
             ErrorKind::NotFound,
             "Error: Missing Lingo.toml file",
         )))),
         (Some(config), ConsoleCommand::Build(build_command_args)) => {
-            CommandResult::Batch(build(&build_command_args, config))
+            CommandResult::Batch(build(&build_command_args, config, false))
         }
         (Some(config), ConsoleCommand::Run(build_command_args)) => {
-            let mut res = build(&build_command_args, config);
-            res.map(|app| {
-                // let mut command = Command::new(app.executable_path());
-                let mut command = Command::new(app.output_root.join("build").join(app.name.clone()));
-                let cfg_file = build_command_args.config_file.as_ref().map(|path| path.to_str().unwrap_or_default());
-                let visualize = build_command_args.visualize;
-
-                command.arg(visualize.to_string());
-
-                if let Some(cfg_file_str) = cfg_file {
-                    command.arg(cfg_file_str);  // Add the config file path if it's present
-                } else {
-                    command.arg("");
-                }
+            let res = build(&build_command_args, config, false).map(|mut res| {
+                res.map(|app| {
+                    let mut command = app_command(app, &build_command_args);
 
-                // log::info!("running command:{:?}", command);
-                liblingo::util::run_and_capture(&mut command)?;
-                Ok(())
+                    // log::info!("running command:{:?}", command);
+                    liblingo::util::run_and_capture(&mut command)?;
+                    Ok(())
+                });
+                res
             });
             CommandResult::Batch(res)
         }
+        (Some(config), ConsoleCommand::Test(build_command_args)) => {
+            let res = build(&build_command_args, config, true);
+            if build_command_args.no_compile {
+                return CommandResult::Batch(res);
+            }
+
+            // apps that exit with an error fail the test
+            CommandResult::Batch(res.map(|mut res| {
+                res.map(|app| {
+                    liblingo::util::execute_command_to_build_result(app_command(
+                        app,
+                        &build_command_args,
+                    ))
+                });
+                res
+            }))
+        }
         (Some(config), ConsoleCommand::Clean) => {
             CommandResult::Batch(run_command(CommandSpec::Clean, config, true))
         }
-        _ => todo!(),
+        (Some(config), ConsoleCommand::Update(update_command_args)) => {
+            CommandResult::Single(do_update(
+                config,
+                &update_command_args.packages,
+                update_command_args.offline || offline_from_env(),
+            ))
+        }
+        (Some(config), ConsoleCommand::Add(add_command_args)) => CommandResult::Single(do_update(
+            config,
+            &[add_command_args.name],
+            add_command_args.offline || offline_from_env(),
+        )),
+        (Some(config), ConsoleCommand::Remove(remove_command_args)) => {
+            // all remaining packages keep the revisions from the lock if there is one
+            let packages = if config.root.join("Lingo.lock").exists() {
+                remove_command_args.packages
+            } else {
+                vec![]
+            };
+
+            CommandResult::Single(do_update(
+                config,
+                &packages,
+                remove_command_args.offline || offline_from_env(),
+            ))
+        }
+        (Some(config), ConsoleCommand::Outdated) => CommandResult::Single(do_outdated(config)),
+        (Some(config), ConsoleCommand::Tree(tree_command_args)) => {
+            CommandResult::Single(do_tree(config, &tree_command_args))
+        }
+        (Some(config), ConsoleCommand::Vendor(vendor_command_args)) => {
+            CommandResult::Single(do_vendor(config, &vendor_command_args))
+        }
+        (Some(config), ConsoleCommand::Package(package_command_args)) => {
+            CommandResult::Single(do_package(config, &package_command_args))
+        }
+        (Some(config), ConsoleCommand::Publish(publish_command_args)) => {
+            CommandResult::Single(do_publish(config, &publish_command_args))
+        }
+        (Some(config), ConsoleCommand::Yank(yank_command_args)) => {
+            CommandResult::Single(do_yank(config, &yank_command_args))
+        }
     }
 }
 
+/// re-resolves the dependencies and writes the Lingo.lock, `packages` limits what is refreshed
+fn do_update(config: &Config, packages: &[String], offline: bool) -> BuildResult {
+    let clone: GitCloneAndCheckoutCap = Box::new(git::clone_and_checkout);
+    let download: DownloadCap = Box::new(do_download);
+    DependencyManager::update(
+        config,
+        config.all_dependencies()?,
+        &config.root.join(OUTPUT_DIRECTORY),
+        packages,
+        offline,
+        &clone,
+        &download,
+    )?;
+    Ok(())
+}
+
+fn do_vendor(config: &Config, args: &VendorArgs) -> BuildResult {
+    let clone: GitCloneAndCheckoutCap = Box::new(git::clone_and_checkout);
+    let download: DownloadCap = Box::new(do_download);
+    DependencyManager::vendor(
+        config,
+        config.all_dependencies()?,
+        &config.root.join(OUTPUT_DIRECTORY),
+        &args.directory,
+        args.offline || offline_from_env(),
+        &clone,
+        &download,
+    )?;
+    Ok(())
+}
+
+fn do_tree(config: &Config, args: &TreeArgs) -> BuildResult {
+    let mut graph = DependencyGraph::from_lock_file(
+        &config.package,
+        &config.all_dependencies()?,
+        &config.root.join("Lingo.lock"),
+    )?;
+    if let Some(package) = &args.invert {
+        graph = graph.invert(package)?;
+    }
+
+    print!("{}", graph.render(args.format)?);
+    Ok(())
+}
+
+fn do_outdated(config: &Config) -> BuildResult {
+    let clone: GitCloneAndCheckoutCap = Box::new(git::clone_and_checkout);
+    let list_tags: GitListTagsCap = Box::new(git::list_tags);
+    let lock = outdated::read_lock(&config.root.join("Lingo.lock"))?;
+    let packages = outdated::outdated(
+        &config.all_dependencies()?,
+        &config.root,
+        &lock,
+        &list_tags,
+        &clone,
+    );
+
+    print!("{}", outdated::format_table(&packages));
+    Ok(())
+}
+
+fn do_package(config: &Config, args: &PackageArgs) -> BuildResult {
+    let clone: GitCloneAndCheckoutCap = Box::new(git::clone_and_checkout);
+    let download: DownloadCap = Box::new(do_download);
+    let packaged = packaging::package(
+        &config.root,
+        &config
+            .root
+            .join(OUTPUT_DIRECTORY)
+            .join(packaging::PACKAGE_DIRECTORY),
+        !args.no_verify,
+        &clone,
+        &download,
+    )?;
+
+    log::info!(
+        "Packaged {} files into {}",
+        packaged.files,
+        packaged.archive.display()
+    );
+    println!("archive:  {}", packaged.archive.display());
+    println!("archive-checksum = \"{}\"", packaged.archive_checksum);
+    println!("checksum = \"{}\"", packaged.checksum);
+    Ok(())
+}
+
+fn do_publish(config: &Config, args: &PublishArgs) -> BuildResult {
+    let clone: GitCloneAndCheckoutCap = Box::new(git::clone_and_checkout);
+    let download: DownloadCap = Box::new(do_download);
+    let registry = publish::select_registry(args.registry.as_deref(), config.registry.as_ref())?;
+    let entry = publish::publish(
+        &config.root,
+        &registry,
+        args.archive_url.as_ref(),
+        args.allow_dirty,
+        &clone,
+        &download,
+    )?;
+
+    log::info!(
+        "Published {} {} from {}",
+        config.package.name,
+        entry.version,
+        entry.source.uri
+    );
+    Ok(())
+}
+
+fn do_yank(config: &Config, args: &YankArgs) -> BuildResult {
+    let clone: GitCloneAndCheckoutCap = Box::new(git::clone_and_checkout);
+    let registry = publish::select_registry(args.registry.as_deref(), config.registry.as_ref())?;
+    publish::yank(
+        &config.package.name,
+        &args.version,
+        &registry,
+        args.undo,
+        &clone,
+    )?;
+    Ok(())
+}
+
 fn do_init(init_config: InitArgs, git_clone_capability: &GitCloneAndCheckoutCap) -> BuildResult {
     // log::info!("Doing Init");
     if Path::new("Lingo.toml").exists() {
@@ -232,7 +457,76 @@ fn do_init(init_config: InitArgs, git_clone_capability: &GitCloneAndCheckoutCap)
     )
 }
 
-fn build<'a>(args: &BuildArgs, config: &'a mut Config) -> BatchBuildResults<'a> {
+fn do_cache(command: CacheCommand) -> BuildResult {
+    let cache = PackageCache::open().ok_or(io::Error::new(
+        ErrorKind::NotFound,
+        "cannot determine the location of the package cache",
+    ))?;
+
+    match command {
+        CacheCommand::List => {
+            for (entry, last_used) in cache.list()? {
+                let days = SystemTime::now()
+                    .duration_since(last_used)
+                    .unwrap_or_default()
+                    .as_secs()
+                    / SECONDS_PER_DAY;
+                println!(
+                    "{} {} {} (used {} days ago)",
+                    entry.name, entry.version, entry.checksum, days
+                );
+            }
+        }
+        CacheCommand::Prune(prune) => {
+            let max_age = Duration::from_secs(prune.older_than * SECONDS_PER_DAY);
+            for entry in cache.prune(max_age)? {
+                println!("Removed {} {}", entry.name, entry.version);
+            }
+        }
+        CacheCommand::Verify => {
+            let mut broken = 0;
+            for (entry, valid) in cache.verify()? {
+                if !valid {
+                    broken += 1;
+                    println!("Removed broken {} {}", entry.name, entry.version);
+                }
+            }
+            println!(
+                "{broken} broken packages found in {}",
+                cache.root().display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// command that runs the binary of the app
+fn app_command(app: &App, args: &BuildArgs) -> Command {
+    // let mut command = Command::new(app.executable_path());
+    let mut command = Command::new(app.output_root.join("build").join(app.name.clone()));
+    let cfg_file = args
+        .config_file
+        .as_ref()
+        .map(|path| path.to_str().unwrap_or_default());
+    let visualize = args.visualize;
+
+    command.arg(visualize.to_string());
+
+    if let Some(cfg_file_str) = cfg_file {
+        command.arg(cfg_file_str); // Add the config file path if it's present
+    } else {
+        command.arg("");
+    }
+
+    command
+}
+
+fn build<'a>(
+    args: &BuildArgs,
+    config: &'a mut Config,
+    dev: bool,
+) -> Result<BatchBuildResults<'a>, Box<AnyError>> {
     run_command(
         CommandSpec::Build(BuildCommandOptions {
             profile: args.build_profile(),
@@ -240,23 +534,31 @@ fn build<'a>(args: &BuildArgs, config: &'a mut Config) -> BatchBuildResults<'a>
             lfc_exec_path: "".into(),
             max_threads: args.threads,
             keep_going: args.keep_going,
+            lock_mode: args.lock_mode(),
+            offline: args.offline || offline_from_env(),
+            dev,
         }),
         config,
         args.keep_going,
     )
 }
 
-fn run_command(task: CommandSpec, config: &mut Config, _fail_at_end: bool) -> BatchBuildResults {
+fn run_command(
+    task: CommandSpec,
+    config: &mut Config,
+    _fail_at_end: bool,
+) -> Result<BatchBuildResults<'_>, Box<AnyError>> {
     let _apps = config.apps.iter().collect::<Vec<_>>();
     liblingo::backends::execute_command(
         &task,
         config,
         Box::new(do_which),
-        Box::new(do_clone_and_checkout),
+        Box::new(git::clone_and_checkout),
+        Box::new(do_download),
     )
 }
 
 enum CommandResult<'a> {
-    Batch(BatchBuildResults<'a>),
+    Batch(Result<BatchBuildResults<'a>, Box<AnyError>>),
     Single(BuildResult),
 }
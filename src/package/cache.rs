@@ -0,0 +1,344 @@
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use versions::Versioning;
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::package::management::{copy_dir_all, package_checksum};
+use crate::package::{deserialize_version, serialize_version};
+use crate::util::hash;
+
+/// environment variable that overrides the location of the package cache
+pub const CACHE_ENV_VARIABLE: &str = "LINGO_CACHE";
+
+/// Metadata stored next to every cached package
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CacheEntry {
+    pub name: String,
+    #[serde(
+        serialize_with = "serialize_version",
+        deserialize_with = "deserialize_version"
+    )]
+    pub version: Versioning,
+    /// checksum of the package content, the cache is addressed by it
+    pub checksum: String,
+    /// source the package was fetched from, if it always yields the same content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// git revision the package was checked out at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// hash of the downloaded archive, only set for tarball sources
+    #[serde(
+        rename = "archive-checksum",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archive_checksum: Option<String>,
+}
+
+/// Maps a source to the checksum of the content it yielded
+#[derive(Deserialize, Serialize, Debug)]
+struct SourceEntry {
+    source: String,
+    checksum: String,
+}
+
+/// hex encoded sha256 of the value, used as file name
+fn digest_name(value: &str) -> String {
+    let digest = Sha256::digest(value.as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// User level cache of fetched packages that is shared between projects. Packages are stored
+/// under `packages/<checksum>` together with a `<checksum>.toml` metadata file, `sources/`
+/// remembers which content a source yielded. All access is guarded by a file lock so multiple
+/// lingo processes can use the cache at the same time.
+pub struct PackageCache {
+    root: PathBuf,
+}
+
+impl PackageCache {
+    /// opens the cache in `$LINGO_CACHE`, `$XDG_CACHE_HOME/lingo` or `~/.cache/lingo`
+    pub fn open() -> Option<PackageCache> {
+        let root = env::var_os(CACHE_ENV_VARIABLE)
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("XDG_CACHE_HOME").map(|path| PathBuf::from(path).join("lingo")))
+            .or_else(|| {
+                env::var_os("HOME").map(|path| PathBuf::from(path).join(".cache").join("lingo"))
+            })?;
+
+        Some(PackageCache::at(root))
+    }
+
+    pub fn at(root: PathBuf) -> PackageCache {
+        PackageCache { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn packages(&self) -> PathBuf {
+        self.root.join("packages")
+    }
+
+    fn package_path(&self, checksum: &str) -> PathBuf {
+        self.packages().join(hash::hex(checksum))
+    }
+
+    fn metadata_path(&self, checksum: &str) -> PathBuf {
+        self.packages()
+            .join(format!("{}.toml", hash::hex(checksum)))
+    }
+
+    fn source_path(&self, source: &str) -> PathBuf {
+        self.root
+            .join("sources")
+            .join(format!("{}.toml", digest_name(source)))
+    }
+
+    /// bare mirror of the git repository at `url` that is shared between projects
+    pub fn git_mirror(&self, url: &str) -> PathBuf {
+        self.root.join("git").join(digest_name(url))
+    }
+
+    /// takes the cache wide lock, shared for readers and exclusive for writers,
+    /// the lock is released when the returned file is dropped
+    pub(crate) fn lock(&self, exclusive: bool) -> io::Result<File> {
+        fs::create_dir_all(&self.root)?;
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.root.join(".lock"))?;
+
+        if exclusive {
+            file.lock()?;
+        } else {
+            file.lock_shared()?;
+        }
+
+        Ok(file)
+    }
+
+    fn read_entry(&self, metadata_path: &Path) -> anyhow::Result<CacheEntry> {
+        Ok(toml::from_str::<CacheEntry>(&fs::read_to_string(
+            metadata_path,
+        )?)?)
+    }
+
+    fn restore_locked(
+        &self,
+        checksum: &str,
+        destination: &Path,
+    ) -> anyhow::Result<Option<CacheEntry>> {
+        let metadata_path = self.metadata_path(checksum);
+        let package_path = self.package_path(checksum);
+
+        if !metadata_path.exists() || !package_path.is_dir() {
+            return Ok(None);
+        }
+
+        let entry = self.read_entry(&metadata_path)?;
+        copy_dir_all(&package_path, destination)?;
+
+        // the modification time of the metadata tells when the package was used last
+        File::options()
+            .write(true)
+            .open(&metadata_path)?
+            .set_modified(SystemTime::now())?;
+
+        Ok(Some(entry))
+    }
+
+    /// copies the package with the given checksum into `destination` if it is cached
+    pub fn restore(
+        &self,
+        checksum: &str,
+        destination: &Path,
+    ) -> anyhow::Result<Option<CacheEntry>> {
+        let _lock = self.lock(false)?;
+        self.restore_locked(checksum, destination)
+    }
+
+    /// copies the package that was previously fetched from `source` into `destination`
+    pub fn restore_source(
+        &self,
+        source: &str,
+        destination: &Path,
+    ) -> anyhow::Result<Option<CacheEntry>> {
+        let _lock = self.lock(false)?;
+
+        let source_path = self.source_path(source);
+        if !source_path.exists() {
+            return Ok(None);
+        }
+
+        let source_entry = toml::from_str::<SourceEntry>(&fs::read_to_string(source_path)?)?;
+        self.restore_locked(&source_entry.checksum, destination)
+    }
+
+    /// adds the package content in `content` to the cache
+    pub fn insert(&self, entry: &CacheEntry, content: &Path) -> anyhow::Result<()> {
+        let _lock = self.lock(true)?;
+
+        let package_path = self.package_path(&entry.checksum);
+        if !package_path.is_dir() {
+            // the content is copied next to its final location and then moved in one step
+            let staging = self
+                .packages()
+                .join(format!(".{}.partial", hash::hex(&entry.checksum)));
+            let _ = fs::remove_dir_all(&staging);
+            copy_dir_all(content, &staging)?;
+            fs::rename(&staging, &package_path)?;
+        }
+
+        fs::write(self.metadata_path(&entry.checksum), toml::to_string(entry)?)?;
+
+        if let Some(source) = &entry.source {
+            let source_path = self.source_path(source);
+            fs::create_dir_all(self.root.join("sources"))?;
+            fs::write(
+                source_path,
+                toml::to_string(&SourceEntry {
+                    source: source.clone(),
+                    checksum: entry.checksum.clone(),
+                })?,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn entries_locked(&self) -> anyhow::Result<Vec<(CacheEntry, SystemTime)>> {
+        let mut entries = Vec::new();
+
+        if !self.packages().is_dir() {
+            return Ok(entries);
+        }
+
+        for file in fs::read_dir(self.packages())? {
+            let path = file?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                let last_used = fs::metadata(&path)?.modified()?;
+                entries.push((self.read_entry(&path)?, last_used));
+            }
+        }
+
+        entries.sort_by(|a, b| (&a.0.name, &a.0.version).cmp(&(&b.0.name, &b.0.version)));
+        Ok(entries)
+    }
+
+    fn remove_locked(&self, entry: &CacheEntry) -> anyhow::Result<()> {
+        let package_path = self.package_path(&entry.checksum);
+        if package_path.exists() {
+            fs::remove_dir_all(package_path)?;
+        }
+        fs::remove_file(self.metadata_path(&entry.checksum))?;
+
+        if let Some(source) = &entry.source {
+            let source_path = self.source_path(source);
+            if source_path.exists() {
+                fs::remove_file(source_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// all cached packages together with the time they were used last
+    pub fn list(&self) -> anyhow::Result<Vec<(CacheEntry, SystemTime)>> {
+        let _lock = self.lock(false)?;
+        self.entries_locked()
+    }
+
+    /// removes all packages that haven't been used for `max_age`
+    pub fn prune(&self, max_age: Duration) -> anyhow::Result<Vec<CacheEntry>> {
+        let _lock = self.lock(true)?;
+        let now = SystemTime::now();
+
+        let mut removed = Vec::new();
+        for (entry, last_used) in self.entries_locked()? {
+            let age = now.duration_since(last_used).unwrap_or_default();
+            if age >= max_age {
+                self.remove_locked(&entry)?;
+                removed.push(entry);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// recomputes the checksum of every cached package, broken packages are removed
+    pub fn verify(&self) -> anyhow::Result<Vec<(CacheEntry, bool)>> {
+        let _lock = self.lock(true)?;
+
+        let mut results = Vec::new();
+        for (entry, _) in self.entries_locked()? {
+            let path = self.package_path(&entry.checksum);
+            let valid = package_checksum(&path).is_ok_and(|checksum| checksum == entry.checksum);
+
+            if !valid {
+                self.remove_locked(&entry)?;
+            }
+            results.push((entry, valid));
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::{library_toml, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn restores_packages_by_checksum_and_source() {
+        let cache_dir = tempdir().unwrap();
+        let cache = PackageCache::at(cache_dir.path().to_path_buf());
+
+        let content = tempdir().unwrap();
+        write(content.path(), "Lingo.toml", &library_toml("lib", "0.1.0"));
+        let checksum = package_checksum(content.path()).unwrap();
+
+        let entry = CacheEntry {
+            name: "lib".to_string(),
+            version: Versioning::new("0.1.0").unwrap(),
+            checksum: checksum.clone(),
+            source: Some("git+https://example.org/lib.git#abc".to_string()),
+            rev: Some("abc".to_string()),
+            archive_checksum: None,
+        };
+        cache.insert(&entry, content.path()).unwrap();
+
+        let destination = tempdir().unwrap();
+        let restored = cache
+            .restore_source("git+https://example.org/lib.git#abc", destination.path())
+            .unwrap()
+            .unwrap();
+        assert_eq!(restored.rev.as_deref(), Some("abc"));
+        assert!(destination.path().join("Lingo.toml").exists());
+
+        assert!(cache
+            .restore("sha256:00", destination.path())
+            .unwrap()
+            .is_none());
+
+        let verified = cache.verify().unwrap();
+        assert_eq!(verified.len(), 1);
+        assert!(verified[0].1);
+
+        assert_eq!(cache.prune(Duration::ZERO).unwrap().len(), 1);
+        assert!(cache.list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use toml_edit::{DocumentMut, Item, Table};
+
+use std::fs;
+use std::path::Path;
+
+use crate::package::tree::PackageDetails;
+use crate::util::errors::LingoError;
+
+fn read(lingo_toml: &Path) -> anyhow::Result<DocumentMut> {
+    Ok(fs::read_to_string(lingo_toml)?.parse::<DocumentMut>()?)
+}
+
+fn dependencies<'a>(
+    document: &'a mut DocumentMut,
+    lingo_toml: &Path,
+) -> anyhow::Result<&'a mut Table> {
+    document
+        .entry("dependencies")
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .ok_or(LingoError::InvalidDependencyTable(lingo_toml.display().to_string()).into())
+}
+
+/// Adds the dependency to the Lingo.toml or replaces the existing entry with the same name.
+/// The rest of the file including comments and formatting stays untouched.
+pub fn add_dependency(
+    lingo_toml: &Path,
+    name: &str,
+    details: &PackageDetails,
+) -> anyhow::Result<()> {
+    let mut document = read(lingo_toml)?;
+
+    // the entry is serialized like the Lingo.toml is deserialized, so both always agree
+    let serialized = toml::to_string(details)?.parse::<DocumentMut>()?;
+    let mut entry = serialized.as_table().clone().into_inline_table();
+    entry.fmt();
+
+    dependencies(&mut document, lingo_toml)?.insert(name, Item::Value(entry.into()));
+    fs::write(lingo_toml, document.to_string())?;
+    Ok(())
+}
+
+/// removes the given dependencies from the Lingo.toml, fails if one of them doesn't exist
+pub fn remove_dependencies(lingo_toml: &Path, names: &[String]) -> anyhow::Result<()> {
+    let mut document = read(lingo_toml)?;
+    let table = dependencies(&mut document, lingo_toml)?;
+
+    let unknown_names = names
+        .iter()
+        .filter(|&name| !table.contains_key(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !unknown_names.is_empty() {
+        return Err(LingoError::UnknownDependencyNames(unknown_names).into());
+    }
+
+    for name in names {
+        table.remove(name);
+    }
+
+    fs::write(lingo_toml, document.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tree::{GitLock, ProjectSource};
+    use tempfile::tempdir;
+    use versions::Requirement;
+
+    #[test]
+    fn edits_keep_comments_and_formatting() {
+        let directory = tempdir().unwrap();
+        let lingo_toml = directory.path().join("Lingo.toml");
+        fs::write(
+            &lingo_toml,
+            "[package]\nname = \"app\" # the app\n\n[dependencies]\n# needed for the timers\nbar = { version = \">=0.1\", path = \"../bar\" }\n",
+        )
+        .unwrap();
+
+        let details = PackageDetails {
+            version: Requirement::new("^1.2").unwrap(),
+            mutual_exclusive: ProjectSource::Git("https://example.org/foo.git".parse().unwrap()),
+            git_tag: Some(GitLock::Tag("v1.2".to_string())),
+            subdir: None,
+            submodules: true,
+            features: Default::default(),
+            git_rev: None,
+            archive_checksum: None,
+            locked_version: None,
+        };
+        add_dependency(&lingo_toml, "foo", &details).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&lingo_toml).unwrap(),
+            "[package]\nname = \"app\" # the app\n\n[dependencies]\n# needed for the timers\nbar = { version = \">=0.1\", path = \"../bar\" }\nfoo = { version = \"^1.2\", git = \"https://example.org/foo.git\", tag = \"v1.2\" }\n"
+        );
+
+        remove_dependencies(&lingo_toml, &["bar".to_string()]).unwrap();
+        assert!(remove_dependencies(&lingo_toml, &["bar".to_string()]).is_err());
+
+        assert_eq!(
+            fs::read_to_string(&lingo_toml).unwrap(),
+            "[package]\nname = \"app\" # the app\n\n[dependencies]\nfoo = { version = \"^1.2\", git = \"https://example.org/foo.git\", tag = \"v1.2\" }\n"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::package::tree::{is_true, PackageDetails};
+use crate::util::errors::LingoError;
+
+/// name of the feature that is enabled unless a dependent opts out
+pub const DEFAULT_FEATURE: &str = "default";
+
+fn enabled() -> bool {
+    true
+}
+
+/// Features a package asks for when it declares a dependency
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct DependencyFeatures {
+    /// the dependency is only used if a feature of the dependent enables it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// features of the dependency that are enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// if the default feature of the dependency is enabled
+    #[serde(
+        rename = "default-features",
+        default = "enabled",
+        skip_serializing_if = "is_true"
+    )]
+    pub default_features: bool,
+}
+
+impl Default for DependencyFeatures {
+    fn default() -> Self {
+        DependencyFeatures {
+            optional: false,
+            features: vec![],
+            default_features: true,
+        }
+    }
+}
+
+/// Package of the dependency graph with its `[features]` table and its dependencies
+pub struct FeatureNode<'a> {
+    pub table: &'a HashMap<String, Vec<String>>,
+    pub dependencies: &'a [(String, PackageDetails)],
+}
+
+impl FeatureNode<'_> {
+    fn dependency(&self, name: &str) -> Option<&PackageDetails> {
+        self.dependencies
+            .iter()
+            .find(|(dependency, _)| dependency == name)
+            .map(|(_, details)| details)
+    }
+}
+
+/// Features of the dependency graph after they were unified
+#[derive(Default, Debug)]
+pub struct UnifiedFeatures {
+    /// enabled features of every package that is used
+    pub features: HashMap<String, BTreeSet<String>>,
+    /// dependencies that are used, optional ones only if a feature enabled them
+    pub edges: HashSet<(String, String)>,
+}
+
+impl UnifiedFeatures {
+    /// features of the package in a stable order
+    pub fn of(&self, package: &str) -> Vec<String> {
+        self.features
+            .get(package)
+            .map(|features| features.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// enables the dependency and the features its declaration asks for
+    fn use_dependency(
+        &mut self,
+        package: &str,
+        name: &str,
+        details: &PackageDetails,
+        queue: &mut Vec<(String, String)>,
+    ) {
+        if !self.edges.insert((package.to_string(), name.to_string())) {
+            return;
+        }
+
+        self.features.entry(name.to_string()).or_default();
+        queue.extend(
+            details
+                .features
+                .features
+                .iter()
+                .map(|feature| (name.to_string(), feature.clone())),
+        );
+        if details.features.default_features {
+            queue.push((name.to_string(), DEFAULT_FEATURE.to_string()));
+        }
+        // the required dependencies are used once the package itself is
+        queue.push((name.to_string(), String::new()));
+    }
+}
+
+/// Enables the `requested` features of the `root` package and everything they enable in
+/// turn. Features of a package are the union of what all of its dependents ask for. A
+/// feature entry names another feature of the package, an optional dependency or a feature
+/// of a dependency as `dependency/feature`.
+pub fn unify(
+    root: &str,
+    requested: &[String],
+    packages: &HashMap<String, FeatureNode>,
+) -> anyhow::Result<UnifiedFeatures> {
+    let mut unified = UnifiedFeatures::default();
+    unified.features.insert(root.to_string(), BTreeSet::new());
+
+    let mut queue: Vec<(String, String)> = requested
+        .iter()
+        .map(|feature| (root.to_string(), feature.clone()))
+        .collect();
+    queue.push((root.to_string(), String::new()));
+    let mut used = HashSet::new();
+
+    while let Some((package, feature)) = queue.pop() {
+        let Some(node) = packages.get(&package) else {
+            continue;
+        };
+
+        if feature.is_empty() {
+            if used.insert(package.clone()) {
+                for (name, details) in node.dependencies {
+                    if !details.features.optional {
+                        unified.use_dependency(&package, name, details, &mut queue);
+                    }
+                }
+            }
+        } else if let Some((dependency, dependency_feature)) = feature.split_once('/') {
+            let details = node
+                .dependency(dependency)
+                .ok_or(LingoError::UnknownFeature(package.clone(), feature.clone()))?;
+            unified.use_dependency(&package, dependency, details, &mut queue);
+            queue.push((dependency.to_string(), dependency_feature.to_string()));
+        } else if let Some(entries) = node.table.get(&feature) {
+            if unified
+                .features
+                .entry(package.clone())
+                .or_default()
+                .insert(feature.clone())
+            {
+                queue.extend(entries.iter().map(|entry| (package.clone(), entry.clone())));
+            }
+        } else if let Some(details) = node
+            .dependency(&feature)
+            .filter(|details| details.features.optional)
+        {
+            unified.use_dependency(&package, &feature, details, &mut queue);
+        } else if feature != DEFAULT_FEATURE {
+            return Err(LingoError::UnknownFeature(package, feature).into());
+        }
+    }
+
+    Ok(unified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependencies(toml: &str) -> Vec<(String, PackageDetails)> {
+        let mut dependencies =
+            Vec::from_iter(toml::from_str::<HashMap<String, PackageDetails>>(toml).unwrap());
+        dependencies.sort_by(|a, b| a.0.cmp(&b.0));
+        dependencies
+    }
+
+    #[test]
+    fn features_are_unified_across_the_graph() {
+        let root_table = HashMap::from([(
+            "viz".to_string(),
+            vec!["bridge".to_string(), "tracing/lttng".to_string()],
+        )]);
+        let root_dependencies = dependencies(
+            "bridge = { version = \">=0.1\", path = \"bridge\", optional = true }\nunused = { version = \">=0.1\", path = \"unused\", optional = true }\ntracing = { version = \">=0.1\", path = \"tracing\", default-features = false }\n",
+        );
+        let bridge_dependencies = dependencies(
+            "tracing = { version = \">=0.1\", path = \"tracing\", features = [\"json\"] }\n",
+        );
+        let tracing_table = HashMap::from([
+            ("default".to_string(), vec!["json".to_string()]),
+            ("json".to_string(), vec![]),
+            ("lttng".to_string(), vec![]),
+        ]);
+        let empty = HashMap::new();
+
+        let packages = HashMap::from([
+            (
+                "plant".to_string(),
+                FeatureNode {
+                    table: &root_table,
+                    dependencies: &root_dependencies,
+                },
+            ),
+            (
+                "bridge".to_string(),
+                FeatureNode {
+                    table: &empty,
+                    dependencies: &bridge_dependencies,
+                },
+            ),
+            (
+                "tracing".to_string(),
+                FeatureNode {
+                    table: &tracing_table,
+                    dependencies: &[],
+                },
+            ),
+        ]);
+
+        let unified = unify("plant", &[], &packages).unwrap();
+        assert_eq!(unified.of("tracing"), Vec::<String>::new());
+        assert!(!unified.features.contains_key("bridge"));
+
+        let unified = unify("plant", &["viz".to_string()], &packages).unwrap();
+        assert_eq!(unified.of("plant"), ["viz"]);
+        assert_eq!(unified.of("tracing"), ["default", "json", "lttng"]);
+        assert!(unified
+            .edges
+            .contains(&("bridge".to_string(), "tracing".to_string())));
+        assert!(!unified.features.contains_key("unused"));
+
+        assert!(unify("plant", &["gui".to_string()], &packages).is_err());
+    }
+}
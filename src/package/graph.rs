@@ -0,0 +1,338 @@
+use indexmap::IndexMap;
+use serde_derive::Serialize;
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::args::TreeFormat;
+use crate::package::lock::DependencyLock;
+use crate::package::tree::PackageDetails;
+use crate::package::PackageDescription;
+use crate::util::errors::LingoError;
+
+/// Package inside the dependency graph
+#[derive(Serialize, Clone, Debug)]
+pub struct GraphNode {
+    pub name: String,
+    pub version: String,
+    /// type of the source the package comes from, `None` for the root package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    pub dependencies: Vec<String>,
+}
+
+impl GraphNode {
+    fn label(&self) -> String {
+        match (&self.source, &self.rev) {
+            (Some(source), Some(rev)) => format!(
+                "{} v{} ({source} {})",
+                self.name,
+                self.version,
+                &rev[..rev.len().min(8)]
+            ),
+            (Some(source), None) => format!("{} v{} ({source})", self.name, self.version),
+            (None, _) => format!("{} v{}", self.name, self.version),
+        }
+    }
+}
+
+/// quotes and backslashes have to be escaped inside quoted graphviz ids
+fn escape(id: &str) -> String {
+    id.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    root: &'a str,
+    packages: Vec<&'a GraphNode>,
+}
+
+/// Dependency graph of a package as it is recorded in its Lingo.toml and Lingo.lock
+#[derive(Debug)]
+pub struct DependencyGraph {
+    /// package the graph is printed from
+    root: String,
+    nodes: IndexMap<String, GraphNode>,
+}
+
+impl DependencyGraph {
+    pub fn new(
+        package: &PackageDescription,
+        dependencies: &[(String, PackageDetails)],
+        lock: &DependencyLock,
+    ) -> DependencyGraph {
+        let mut direct: Vec<String> = dependencies.iter().map(|(name, _)| name.clone()).collect();
+        // optional dependencies are only in the graph if a feature enabled them
+        direct.retain(|name| lock.dependencies.contains_key(name));
+        direct.sort();
+        direct.dedup();
+
+        let mut nodes = IndexMap::new();
+        nodes.insert(
+            package.name.clone(),
+            GraphNode {
+                name: package.name.clone(),
+                version: package.version.to_string(),
+                source: None,
+                rev: None,
+                dependencies: direct,
+            },
+        );
+
+        for (name, lock) in &lock.dependencies {
+            let mut dependencies = lock.dependencies.clone();
+            dependencies.sort();
+
+            nodes.insert(
+                name.clone(),
+                GraphNode {
+                    name: name.clone(),
+                    version: lock.version.to_string(),
+                    source: Some(lock.source.source_type.to_string()),
+                    rev: lock.source.rev.clone(),
+                    dependencies,
+                },
+            );
+        }
+
+        DependencyGraph {
+            root: package.name.clone(),
+            nodes,
+        }
+    }
+
+    /// reads the graph from the Lingo.lock, which has to match the dependencies
+    pub fn from_lock_file(
+        package: &PackageDescription,
+        dependencies: &[(String, PackageDetails)],
+        lock_file: &Path,
+    ) -> anyhow::Result<DependencyGraph> {
+        if !lock_file.exists() {
+            return Err(LingoError::LockFileRequired("there is no Lingo.lock".to_string()).into());
+        }
+
+        let lock = DependencyLock::read(lock_file)?;
+        lock.check_up_to_date(dependencies, true)
+            .map_err(|e| LingoError::LockFileRequired(e.to_string()))?;
+
+        Ok(DependencyGraph::new(package, dependencies, &lock))
+    }
+
+    /// graph with all edges reversed that starts at `package`, it shows who pulls it in
+    pub fn invert(&self, package: &str) -> anyhow::Result<DependencyGraph> {
+        if !self.nodes.contains_key(package) {
+            return Err(LingoError::UnknownDependencyNames(vec![package.to_string()]).into());
+        }
+
+        let mut nodes: IndexMap<String, GraphNode> = self
+            .nodes
+            .iter()
+            .map(|(name, node)| {
+                let mut inverted = node.clone();
+                inverted.dependencies.clear();
+                (name.clone(), inverted)
+            })
+            .collect();
+
+        for node in self.nodes.values() {
+            for dependency in &node.dependencies {
+                if let Some(inverted) = nodes.get_mut(dependency) {
+                    inverted.dependencies.push(node.name.clone());
+                }
+            }
+        }
+
+        Ok(DependencyGraph {
+            root: package.to_string(),
+            nodes,
+        })
+    }
+
+    /// packages that can be reached from the root including the root itself
+    fn reachable(&self) -> Vec<&GraphNode> {
+        let mut reachable: Vec<&GraphNode> = self.nodes.get(&self.root).into_iter().collect();
+        let mut index = 0;
+
+        while let Some(node) = reachable.get(index) {
+            for dependency in &node.dependencies {
+                if let Some(dependency) = self.nodes.get(dependency) {
+                    if !reachable.iter().any(|known| known.name == dependency.name) {
+                        reachable.push(dependency);
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        reachable
+    }
+
+    fn write_children(
+        &self,
+        node: &GraphNode,
+        prefix: &str,
+        printed: &mut HashSet<String>,
+        output: &mut String,
+    ) {
+        let children: Vec<&GraphNode> = node
+            .dependencies
+            .iter()
+            .filter_map(|name| self.nodes.get(name))
+            .collect();
+
+        for (index, child) in children.iter().enumerate() {
+            let last = index + 1 == children.len();
+            let branch = if last { "└── " } else { "├── " };
+
+            // packages that were already printed are marked and not expanded again
+            if !printed.insert(child.name.clone()) {
+                let _ = writeln!(output, "{prefix}{branch}{} (*)", child.label());
+                continue;
+            }
+
+            let _ = writeln!(output, "{prefix}{branch}{}", child.label());
+            let indent = if last { "    " } else { "│   " };
+            self.write_children(child, &format!("{prefix}{indent}"), printed, output);
+        }
+    }
+
+    /// Tree that starts at the root. Packages that appear more than once are marked with
+    /// `(*)`, their dependencies are only shown the first time.
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        if let Some(root) = self.nodes.get(&self.root) {
+            let _ = writeln!(output, "{}", root.label());
+            let mut printed = HashSet::from([root.name.clone()]);
+            self.write_children(root, "", &mut printed, &mut output);
+        }
+        output
+    }
+
+    /// graphviz description of the graph
+    pub fn to_dot(&self) -> String {
+        let mut output = String::from("digraph dependencies {\n");
+        let reachable = self.reachable();
+
+        for node in &reachable {
+            let _ = writeln!(
+                output,
+                "    \"{}\" [label=\"{}\"];",
+                escape(&node.name),
+                escape(&node.label())
+            );
+        }
+        for node in &reachable {
+            for dependency in &node.dependencies {
+                let _ = writeln!(
+                    output,
+                    "    \"{}\" -> \"{}\";",
+                    escape(&node.name),
+                    escape(dependency)
+                );
+            }
+        }
+
+        output.push_str("}\n");
+        output
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(&JsonGraph {
+            root: &self.root,
+            packages: self.reachable(),
+        })?)
+    }
+
+    pub fn render(&self, format: TreeFormat) -> anyhow::Result<String> {
+        match format {
+            TreeFormat::Text => Ok(self.to_text()),
+            TreeFormat::Dot => Ok(self.to_dot()),
+            TreeFormat::Json => self.to_json(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use versions::Versioning;
+
+    fn graph() -> DependencyGraph {
+        let lock = DependencyLock::parse(
+            r#"
+[lib1]
+name = "lib1"
+version = "0.1.0"
+source = "path+\"/lib1\""
+checksum = "sha256:01"
+dependencies = ["lib2"]
+
+[lib2]
+name = "lib2"
+version = "0.2.0"
+source = "git+https://example.org/lib2.git#f372e81440da054de7444ee3ab16e6c1b3d4793c"
+checksum = "sha256:02"
+"#,
+        )
+        .unwrap();
+
+        let package = PackageDescription {
+            name: "app".to_string(),
+            version: Versioning::new("1.0.0").unwrap(),
+            authors: None,
+            website: None,
+            license: None,
+            description: None,
+            exclude: vec![],
+            include: vec![],
+        };
+
+        let mut graph = DependencyGraph::new(&package, &[], &lock);
+        graph.nodes[0].dependencies = vec!["lib1".to_string(), "lib2".to_string()];
+        graph
+    }
+
+    #[test]
+    fn marks_packages_that_appear_twice() {
+        assert_eq!(
+            graph().to_text(),
+            "app v1.0.0\n├── lib1 v0.1.0 (path)\n│   └── lib2 v0.2.0 (git f372e814)\n└── lib2 v0.2.0 (git f372e814) (*)\n"
+        );
+    }
+
+    #[test]
+    fn inverted_graph_shows_dependents() {
+        let inverted = graph().invert("lib2").unwrap();
+        assert_eq!(
+            inverted.to_text(),
+            "lib2 v0.2.0 (git f372e814)\n├── app v1.0.0\n└── lib1 v0.1.0 (path)\n    └── app v1.0.0 (*)\n"
+        );
+        assert!(inverted.to_dot().contains("\"lib1\" -> \"app\";"));
+        assert!(graph().invert("unknown").is_err());
+    }
+
+    #[test]
+    fn skipped_children_do_not_hide_the_last_branch() {
+        let mut graph = graph();
+        graph.nodes[0].dependencies.push("missing".to_string());
+        assert!(graph
+            .to_text()
+            .ends_with("└── lib2 v0.2.0 (git f372e814) (*)\n"));
+    }
+
+    #[test]
+    fn dot_output_escapes_names() {
+        let mut graph = graph();
+        let name = "lib\"1\\".to_string();
+        let mut lib1 = graph.nodes.shift_remove("lib1").unwrap();
+        lib1.name = name.clone();
+        graph.nodes.insert(name.clone(), lib1);
+        graph.nodes[0].dependencies[0] = name;
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"lib\\\"1\\\\\" [label=\"lib\\\"1\\\\ v0.1.0 (path)\"];"));
+        assert!(dot.contains("\"app\" -> \"lib\\\"1\\\\\";"));
+    }
+}
@@ -1,34 +1,38 @@
-use crate::util::sha1dir;
 use colored::Colorize;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use versions::Versioning;
+use versions::{Requirement, Versioning};
 
-use log::{error, info};
+use log::{error, warn};
 use serde::de::Error as DeserializationError;
 use serde::ser::Error as SerializationError;
 use std::cmp::PartialEq;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use indexmap::IndexMap;
 
-use crate::GitCloneAndCheckoutCap;
+use crate::{DownloadCap, GitCloneAndCheckoutCap};
 
-use crate::package::management::copy_dir_all;
+use crate::package::cache::{CacheEntry, PackageCache};
+use crate::package::management::{
+    copy_dir_all, is_network_access_offline, package_root, verify_package_checksum,
+};
+use crate::package::vendor::VendorConfig;
 use crate::package::{
     deserialize_version, serialize_version,
     target_properties::{LibraryTargetProperties, MergeTargetProperties},
-    tree::{DependencyTreeNode, PackageDetails, ProjectSource},
+    tree::{DependencyTreeNode, GitLock, PackageDetails, ProjectSource},
     ConfigFile,
 };
 use crate::util::errors::LingoError;
+use crate::util::hash::{self, Algorithm};
 
 pub struct ParseLockSourceError {}
 
 /// Different package sources types, available inside the lock file.
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub enum PackageLockSourceType {
     REGISTRY,
     GIT,
@@ -37,11 +41,86 @@ pub enum PackageLockSourceType {
 }
 
 /// Struct that saves the source uri string
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PackageLockSource {
     pub source_type: PackageLockSourceType,
     pub uri: String,
     pub rev: Option<String>,
+    /// directory inside the repository or archive that contains the package
+    pub subdir: Option<PathBuf>,
+    /// if the submodules of the git repository are part of the package
+    pub submodules: bool,
+    /// tag, branch or revision the git dependency was declared with
+    pub git_ref: Option<GitLock>,
+}
+
+fn join_options(options: Vec<String>) -> String {
+    if options.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", options.join("&"))
+    }
+}
+
+impl PackageLockSource {
+    /// Options that change which content the source yields, written as query behind the url
+    /// like `?subdir=libs/sensors&submodules=false`. Empty if all options have their defaults.
+    pub fn options(&self) -> String {
+        join_options(self.content_options())
+    }
+
+    fn content_options(&self) -> Vec<String> {
+        let mut options = vec![];
+        if let Some(subdir) = &self.subdir {
+            options.push(format!("subdir={}", subdir.display()));
+        }
+        if !self.submodules {
+            options.push("submodules=false".to_string());
+        }
+        options
+    }
+
+    /// the options together with the declared git reference like `?subdir=libs&tag=v1.0`
+    fn options_with_ref(&self) -> String {
+        let mut options = self.content_options();
+        match &self.git_ref {
+            Some(GitLock::Tag(tag)) => options.push(format!("tag={tag}")),
+            Some(GitLock::Branch(branch)) => options.push(format!("branch={branch}")),
+            Some(GitLock::Rev(rev)) => options.push(format!("rev={rev}")),
+            None => {}
+        }
+        join_options(options)
+    }
+
+    /// splits the options from the end of the uri, urls with their own query stay untouched
+    fn parse_options(&mut self) {
+        let Some((uri, query)) = self.uri.rsplit_once('?') else {
+            return;
+        };
+
+        let options: Option<Vec<(&str, &str)>> = query
+            .split('&')
+            .map(|option| option.split_once('='))
+            .collect();
+        let Some(options) = options.filter(|options| {
+            options
+                .iter()
+                .all(|(key, _)| ["subdir", "submodules", "tag", "branch", "rev"].contains(key))
+        }) else {
+            return;
+        };
+
+        for (key, value) in options {
+            match key {
+                "subdir" => self.subdir = Some(PathBuf::from(value)),
+                "submodules" => self.submodules = value != "false",
+                "tag" => self.git_ref = Some(GitLock::Tag(value.to_string())),
+                "branch" => self.git_ref = Some(GitLock::Branch(value.to_string())),
+                _ => self.git_ref = Some(GitLock::Rev(value.to_string())),
+            }
+        }
+        self.uri = uri.to_string();
+    }
 }
 
 // Tries to parse the enum value from given string
@@ -78,12 +157,13 @@ impl From<ProjectSource> for PackageLockSourceType {
             ProjectSource::Git(_) => Self::GIT,
             ProjectSource::TarBall(_) => Self::TARBALL,
             ProjectSource::Path(_) => Self::PATH,
+            ProjectSource::Registry(_) => Self::REGISTRY,
         }
     }
 }
 
 /// Parses the whole source uri string of a package
-/// the uri string follows the pattern <type>+<url>(#<git-rev>)
+/// the uri string follows the pattern <type>+<url>(?<options>)(#<git-rev>)
 impl FromStr for PackageLockSource {
     type Err = ParseLockSourceError;
 
@@ -102,21 +182,32 @@ impl FromStr for PackageLockSource {
                             return Err(ParseLockSourceError {});
                         }
                     },
+                    // older versions of lingo wrote paths in quotes
+                    PackageLockSourceType::PATH => {
+                        uri = uri.trim_matches('"');
+                        None
+                    }
                     _ => None,
                 };
 
-                Ok(PackageLockSource {
+                let mut source = PackageLockSource {
                     source_type,
                     uri: uri.to_string(),
                     rev,
-                })
+                    subdir: None,
+                    submodules: true,
+                    git_ref: None,
+                };
+                source.parse_options();
+
+                Ok(source)
             }
             None => Err(ParseLockSourceError {}),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct PackageLock {
     pub name: String,
     #[serde(
@@ -126,25 +217,83 @@ pub struct PackageLock {
     pub version: Versioning,
     pub source: PackageLockSource,
     pub checksum: String,
+    /// hash of the downloaded archive, only set for tarball sources
+    #[serde(
+        rename = "archive-checksum",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archive_checksum: Option<String>,
+    /// names of the packages this package depends on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    /// features that are enabled for this package
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// directory inside the package with the library sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<PathBuf>,
+    /// cmake file of the library relative to the package root
+    #[serde(
+        rename = "cmake-include",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cmake_include: Option<PathBuf>,
 }
 
-impl From<DependencyTreeNode> for PackageLock {
-    fn from(value: DependencyTreeNode) -> Self {
-        let uri = match &value.package.mutual_exclusive {
+impl PackageLock {
+    /// version of the package together with the git revision if there is one
+    fn display_version(&self) -> String {
+        match &self.source.rev {
+            Some(rev) => format!("v{} ({})", self.version, &rev[..rev.len().min(8)]),
+            None => format!("v{}", self.version),
+        }
+    }
+}
+
+impl From<&PackageDetails> for PackageLockSource {
+    fn from(value: &PackageDetails) -> Self {
+        let uri = match &value.mutual_exclusive {
             ProjectSource::Git(git) => git.to_string(),
             ProjectSource::TarBall(tar) => tar.to_string(),
-            ProjectSource::Path(path) => format!("{:?}", path),
+            ProjectSource::Path(path) => path.display().to_string(),
+            ProjectSource::Registry(registry) => registry
+                .as_ref()
+                .map(|url| url.to_string())
+                .unwrap_or_default(),
         };
 
+        PackageLockSource {
+            source_type: PackageLockSourceType::from(value.mutual_exclusive.clone()),
+            uri,
+            rev: value.git_rev.clone(),
+            subdir: value.subdir.clone(),
+            submodules: value.submodules,
+            git_ref: match &value.mutual_exclusive {
+                ProjectSource::Git(_) => value.git_tag.clone(),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl From<DependencyTreeNode> for PackageLock {
+    fn from(value: DependencyTreeNode) -> Self {
         PackageLock {
+            source: PackageLockSource::from(&value.package),
             name: value.name,
             version: value.version,
-            source: PackageLockSource {
-                source_type: PackageLockSourceType::from(value.package.mutual_exclusive),
-                uri,
-                rev: value.package.git_rev,
-            },
             checksum: value.hash,
+            archive_checksum: value.package.archive_checksum,
+            dependencies: value
+                .dependencies
+                .into_iter()
+                .map(|dependency| dependency.name)
+                .collect(),
+            features: value.features,
+            location: Some(value.include_path),
+            cmake_include: value.properties.cmake_include_file,
         }
     }
 }
@@ -168,7 +317,8 @@ impl Serialize for PackageLockSource {
         S: Serializer,
     {
         let source_type = self.source_type.to_string();
-        let mut serialized_string = format!("{}+{}", source_type, self.uri);
+        let mut serialized_string =
+            format!("{}+{}{}", source_type, self.uri, self.options_with_ref());
 
         if self.source_type == PackageLockSourceType::GIT {
             if let Some(rev) = self.rev.clone() {
@@ -183,19 +333,256 @@ impl Serialize for PackageLockSource {
     }
 }
 
-#[derive(Deserialize, Serialize, Default, Debug)]
+/// How the Lingo.lock is treated while building
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// the lock is recreated if it doesn't match the Lingo.toml
+    #[default]
+    Update,
+    /// the lock has to be up to date
+    Locked,
+    /// the lock has to be up to date and no package is fetched over the network
+    Frozen,
+}
+
+/// Splits a text with git conflict markers into the two sides of the conflicts, the common
+/// ancestor of diff3 style conflicts is dropped. Returns None if there are no conflicts.
+fn split_conflicts(text: &str) -> Option<(String, String)> {
+    #[derive(PartialEq)]
+    enum Side {
+        Both,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let is_marker = |line: &str, marker: &str| {
+        line.strip_prefix(marker)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+    };
+
+    let mut side = Side::Both;
+    let mut conflicts = false;
+    let (mut ours, mut theirs) = (String::new(), String::new());
+
+    for line in text.lines() {
+        if is_marker(line, "<<<<<<<") {
+            conflicts = true;
+            side = Side::Ours;
+        } else if side == Side::Ours && is_marker(line, "|||||||") {
+            side = Side::Base;
+        } else if (side == Side::Ours || side == Side::Base) && is_marker(line, "=======") {
+            side = Side::Theirs;
+        } else if side == Side::Theirs && is_marker(line, ">>>>>>>") {
+            side = Side::Both;
+        } else {
+            if side == Side::Both || side == Side::Ours {
+                ours.push_str(line);
+                ours.push('\n');
+            }
+            if side == Side::Both || side == Side::Theirs {
+                theirs.push_str(line);
+                theirs.push('\n');
+            }
+        }
+    }
+
+    conflicts.then_some((ours, theirs))
+}
+
+/// format version of the Lingo.lock that is written, older versions are migrated when read
+pub const LOCK_VERSION: i64 = 2;
+
+/// Layout of the Lingo.lock on disk
+#[derive(Deserialize, Serialize)]
+struct LockFile {
+    version: i64,
+    /// features of the root package the dependencies were resolved with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    features: Vec<String>,
+    #[serde(rename = "package", default)]
+    packages: Vec<PackageLock>,
+}
+
+#[derive(Default, Debug)]
 pub struct DependencyLock {
     /// mapping from package name to location
-    #[serde(flatten)]
     pub dependencies: IndexMap<String, PackageLock>,
 
+    /// format version the lock was read in
+    version: i64,
+
+    /// features of the root package the dependencies were resolved with
+    features: Vec<String>,
+
+    /// the lock had git conflict markers and only holds the packages both sides agree on
+    conflicted: bool,
+
+    /// locked packages the current build doesn't need, they are not fetched
+    skipped: HashSet<String>,
+
     /// this will be populated when the project is successfully loaded from the lock file
-    #[serde(skip)]
     loaded_dependencies: Vec<DependencyTreeNode>,
 }
 
 impl DependencyLock {
-    pub(crate) fn create(selected_dependencies: Vec<DependencyTreeNode>) -> DependencyLock {
+    /// Parses the Lingo.lock at `path`. Locks without a version are the flat tables written by
+    /// older versions of lingo and are migrated, locks from newer versions are rejected.
+    pub fn read(path: &Path) -> anyhow::Result<DependencyLock> {
+        let text = fs::read_to_string(path)?;
+        DependencyLock::parse(&text).map_err(|e| match e.downcast_ref::<LingoError>() {
+            Some(_) => e,
+            None => LingoError::InvalidLockFile(path.display().to_string(), e.to_string()).into(),
+        })
+    }
+
+    /// parses the contents of a Lingo.lock, see [`DependencyLock::read`]
+    pub fn parse(text: &str) -> anyhow::Result<DependencyLock> {
+        if let Some((ours, theirs)) = split_conflicts(text) {
+            return Ok(DependencyLock::parse(&ours)?.agreement(DependencyLock::parse(&theirs)?));
+        }
+
+        let table = toml::from_str::<toml::Table>(text)?;
+
+        let (version, features, dependencies) = match table.get("version") {
+            // a package called version in a legacy lock is a table
+            None | Some(toml::Value::Table(_)) => (
+                1,
+                vec![],
+                toml::from_str::<IndexMap<String, PackageLock>>(text)?,
+            ),
+            Some(toml::Value::Integer(version)) if *version > LOCK_VERSION => {
+                return Err(LingoError::LockFileTooNew(*version, LOCK_VERSION).into());
+            }
+            Some(toml::Value::Integer(LOCK_VERSION)) => {
+                let file = toml::from_str::<LockFile>(text)?;
+                let mut dependencies = IndexMap::new();
+                for package in file.packages {
+                    if dependencies.contains_key(&package.name) {
+                        return Err(anyhow::anyhow!("{} is locked twice", package.name));
+                    }
+                    dependencies.insert(package.name.clone(), package);
+                }
+                (LOCK_VERSION, file.features, dependencies)
+            }
+            Some(version) => return Err(anyhow::anyhow!("unknown format version {version}")),
+        };
+
+        Ok(DependencyLock {
+            dependencies,
+            version,
+            features,
+            ..Default::default()
+        })
+    }
+
+    /// if the lock was read in an older format and is rewritten once it is written again
+    pub fn has_legacy_format(&self) -> bool {
+        self.version < LOCK_VERSION
+    }
+
+    /// if the lock had merge conflicts, see [`DependencyLock::agreement`]
+    pub fn has_conflicts(&self) -> bool {
+        self.conflicted
+    }
+
+    /// Lock with the packages that both sides of a merge conflict locked identically, all other
+    /// packages have to be resolved again.
+    fn agreement(mut self, theirs: DependencyLock) -> DependencyLock {
+        self.dependencies.retain(|name, ours| {
+            theirs.dependencies.get(name).is_some_and(|other| {
+                ours.version == other.version
+                    && ours.source == other.source
+                    && ours.checksum == other.checksum
+            })
+        });
+        self.version = self.version.min(theirs.version);
+        self.conflicted = true;
+        self
+    }
+
+    /// names of the locked packages the given packages depend on, including themselves
+    pub fn reachable(&self, roots: &[String]) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut queue = roots.to_vec();
+        while let Some(name) = queue.pop() {
+            match self.dependencies.get(&name) {
+                Some(lock) if !reachable.contains(&name) => {
+                    queue.extend(lock.dependencies.iter().cloned());
+                    reachable.insert(name);
+                }
+                _ => {}
+            }
+        }
+        reachable
+    }
+
+    /// only the given packages and their dependencies are fetched by [`DependencyLock::init`]
+    pub fn skip_unreachable(&mut self, roots: &[String]) {
+        let reachable = self.reachable(roots);
+        self.skipped = self
+            .dependencies
+            .keys()
+            .filter(|name| !reachable.contains(*name))
+            .cloned()
+            .collect();
+    }
+
+    /// Adds locked packages the current build doesn't need without fetching them. Returns false
+    /// if the lock has one of them in a different version, source or with other features.
+    pub(crate) fn keep(&mut self, packages: Vec<PackageLock>) -> bool {
+        let agrees = packages.iter().all(|kept| {
+            self.dependencies.get(&kept.name).is_none_or(|lock| {
+                lock.version == kept.version
+                    && lock.source == kept.source
+                    && lock.features == kept.features
+                    && lock.dependencies == kept.dependencies
+            })
+        });
+        if !agrees {
+            return false;
+        }
+
+        for package in packages {
+            if !self.dependencies.contains_key(&package.name) {
+                self.skipped.insert(package.name.clone());
+                self.dependencies.insert(package.name.clone(), package);
+            }
+        }
+        true
+    }
+
+    /// locked git revision of every package that has one
+    pub(crate) fn revisions(&self) -> HashMap<String, String> {
+        self.dependencies
+            .iter()
+            .filter_map(|(name, lock)| lock.source.rev.clone().map(|rev| (name.clone(), rev)))
+            .collect()
+    }
+
+    /// locked archive checksum of every tarball package together with its source
+    pub(crate) fn archive_checksums(&self) -> HashMap<String, (PackageLockSource, String)> {
+        self.dependencies
+            .iter()
+            .filter_map(|(name, lock)| {
+                let checksum = lock.archive_checksum.clone()?;
+                Some((name.clone(), (lock.source.clone(), checksum)))
+            })
+            .collect()
+    }
+
+    /// locked version of every package
+    pub(crate) fn versions(&self) -> HashMap<String, Versioning> {
+        self.dependencies
+            .iter()
+            .map(|(name, lock)| (name.clone(), lock.version.clone()))
+            .collect()
+    }
+
+    pub(crate) fn create(
+        selected_dependencies: Vec<DependencyTreeNode>,
+        features: &[String],
+    ) -> DependencyLock {
         let mut map = IndexMap::new();
         for dependency in &selected_dependencies {
             map.insert(
@@ -206,30 +593,212 @@ impl DependencyLock {
 
         Self {
             dependencies: map,
+            version: LOCK_VERSION,
+            features: features.to_vec(),
+            conflicted: false,
+            skipped: HashSet::new(),
             loaded_dependencies: selected_dependencies,
         }
     }
 
+    /// Checks that the lock still describes the given dependencies of the root package. With
+    /// `strict` packages that nobody depends on anymore also count as outdated.
+    pub fn check_up_to_date(
+        &self,
+        dependencies: &[(String, PackageDetails)],
+        strict: bool,
+    ) -> anyhow::Result<()> {
+        if self.conflicted {
+            return Err(
+                LingoError::LockFileOutdated("Lingo.lock has merge conflicts".to_string()).into(),
+            );
+        }
+
+        for (name, details) in dependencies {
+            let Some(lock) = self.dependencies.get(name) else {
+                // optional dependencies are only locked if a requested feature enables them
+                if details.features.optional {
+                    continue;
+                }
+                return Err(LingoError::LockFileOutdated(format!(
+                    "{name} is not in the lock file"
+                ))
+                .into());
+            };
+
+            if !details.version.matches(&lock.version) {
+                return Err(LingoError::LockFileOutdated(format!(
+                    "locked version {} of {name} doesn't match requirement {}",
+                    lock.version, details.version
+                ))
+                .into());
+            }
+
+            // older locks didn't record the tag, branch or revision a git dependency asked for
+            let declared = PackageLockSource::from(details);
+            if declared.source_type != lock.source.source_type
+                || declared.uri != lock.source.uri
+                || (!self.has_legacy_format() && declared.git_ref != lock.source.git_ref)
+            {
+                return Err(
+                    LingoError::LockFileOutdated(format!("source of {name} changed")).into(),
+                );
+            }
+
+            if details.subdir != lock.source.subdir || details.submodules != lock.source.submodules
+            {
+                return Err(LingoError::LockFileOutdated(format!(
+                    "subdirectory or submodules of {name} changed"
+                ))
+                .into());
+            }
+        }
+
+        if strict {
+            let mut reachable: Vec<&String> = dependencies.iter().map(|(name, _)| name).collect();
+            let mut index = 0;
+            while let Some(name) = reachable.get(index) {
+                if let Some(lock) = self.dependencies.get(*name) {
+                    for dependency in &lock.dependencies {
+                        if !reachable.contains(&dependency) {
+                            reachable.push(dependency);
+                        }
+                    }
+                }
+                index += 1;
+            }
+
+            let unused = self
+                .dependencies
+                .keys()
+                .find(|name| !reachable.contains(name));
+            if let Some(name) = unused {
+                return Err(LingoError::LockFileOutdated(format!(
+                    "{name} is not required anymore"
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// checks that the lock was resolved for the `requested` features of the root package
+    pub fn check_features(&self, requested: &[String]) -> anyhow::Result<()> {
+        if self.features != requested {
+            return Err(LingoError::LockFileOutdated("features changed".to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// checks if any checksum was written by an older version of lingo
+    pub fn has_legacy_checksums(&self) -> bool {
+        self.dependencies.values().any(|lock| {
+            Algorithm::of(&lock.checksum) != Algorithm::Sha256
+                || lock
+                    .archive_checksum
+                    .as_ref()
+                    .is_some_and(|checksum| Algorithm::of(checksum) != Algorithm::Sha256)
+        })
+    }
+
+    /// writes the lock file down
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file = LockFile {
+            version: LOCK_VERSION,
+            features: self.features.clone(),
+            packages: self.dependencies.values().cloned().collect(),
+        };
+        let serialized_toml = toml::to_string(&file)?;
+        fs::write(path, serialized_toml)?;
+        Ok(())
+    }
+
+    /// Fetches the locked packages into the include folder of the output directory
+    /// `target_path` unless they are there already and verifies their checksums.
     pub fn init(
         &mut self,
-        lfc_include_folder: &Path,
+        target_path: &Path,
+        offline: bool,
+        cache: Option<&PackageCache>,
+        vendored: &VendorConfig,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
     ) -> anyhow::Result<()> {
-        for (_, lock) in self.dependencies.iter() {
+        let mut missing = vec![];
+        let lfc_include_folder = target_path.join("lfc_include");
+
+        for (_, lock) in self.dependencies.iter_mut() {
+            if self.skipped.contains(&lock.name) {
+                continue;
+            }
+
             let temp = lfc_include_folder.join(&lock.name);
             // the Lingo.toml for this dependency doesnt exists, hence we need to fetch this package
+            let mut fetched = false;
             if !temp.join("Lingo.toml").exists() {
-                let mut details = PackageDetails::try_from(&lock.source)?;
+                // vendored copies are preferred over the cache and the package source
+                let restored = match vendored.locked(lock) {
+                    Some(package) => {
+                        copy_dir_all(vendored.path(package), &temp)?;
+                        true
+                    }
+                    None => cache
+                        .map(|cache| cache.restore(&lock.checksum, &temp))
+                        .transpose()
+                        .unwrap_or_else(|e| {
+                            warn!("ignoring package cache: {e}");
+                            None
+                        })
+                        .flatten()
+                        .is_some(),
+                };
+
+                if !restored {
+                    let mut details = PackageDetails::try_from(&lock.source)?;
+                    details.version =
+                        Requirement::new(&format!("={}", lock.version)).unwrap_or_default();
+                    details.archive_checksum = lock.archive_checksum.clone();
+                    details.locked_version = Some(lock.version.clone());
+                    details.anchor(package_root(target_path));
+
+                    match details.fetch(
+                        &lock.name,
+                        &temp,
+                        offline,
+                        git_clone_and_checkout_cap,
+                        download_cap,
+                    ) {
+                        Ok(()) => {}
+                        // all packages that are missing are reported together
+                        Err(e) if is_network_access_offline(&e) => {
+                            missing.push(lock.name.clone());
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    }
 
-                details
-                    .fetch(&temp, git_clone_and_checkout_cap)
-                    .expect("cannot pull package");
+                    lock.archive_checksum = details.archive_checksum;
+                    fetched = true;
+                }
             }
 
-            let hash = sha1dir::checksum_current_dir(&temp, false);
+            // legacy checksums are replaced and end up in the next written lock
+            lock.checksum = verify_package_checksum(&lock.name, &temp, &lock.checksum)?;
+
+            if let (Some(cache), true) = (cache, fetched) {
+                let entry = CacheEntry {
+                    name: lock.name.clone(),
+                    version: lock.version.clone(),
+                    checksum: lock.checksum.clone(),
+                    source: None,
+                    rev: lock.source.rev.clone(),
+                    archive_checksum: lock.archive_checksum.clone(),
+                };
 
-            if hash.to_string() != lock.checksum {
-                println!("checksum does not match aborting!");
+                if let Err(e) = cache.insert(&entry, &temp) {
+                    warn!("cannot add {} to the package cache: {e}", lock.name);
+                }
             }
 
             let lingo_toml_text = fs::read_to_string(temp.join("Lingo.toml"))?;
@@ -253,6 +822,15 @@ impl DependencyLock {
                 }
             };
 
+            // metadata is taken from the package itself, which also fills it in for migrated locks
+            let include_path = lib
+                .location
+                .strip_prefix(&temp)
+                .unwrap_or(&lib.location)
+                .to_path_buf();
+            lock.location = Some(include_path.clone());
+            lock.cmake_include = lib.properties.cmake_include_file.clone();
+
             self.loaded_dependencies.push(DependencyTreeNode {
                 name: read_toml.package.name.clone(),
                 version: read_toml.package.version.clone(),
@@ -260,30 +838,46 @@ impl DependencyLock {
                     version: Default::default(),
                     mutual_exclusive: ProjectSource::Path(PathBuf::new()),
                     git_tag: None,
+                    subdir: None,
+                    submodules: true,
+                    features: Default::default(),
                     git_rev: None,
+                    archive_checksum: None,
+                    locked_version: None,
                 },
                 location: temp.clone(),
-                include_path: lib.location.clone(),
+                include_path,
                 hash: lock.checksum.clone(),
                 dependencies: vec![],
                 properties: lib.properties.clone(),
+                feature_table: read_toml.features.clone(),
+                declared: vec![],
+                features: lock.features.clone(),
             });
         }
 
+        if !missing.is_empty() {
+            return Err(LingoError::PackagesNotAvailableOffline(missing).into());
+        }
+
         Ok(())
     }
 
     pub fn create_library_folder(
         &self,
-        include_path: &PathBuf,
+        include_path: &Path,
         source_path: &Path,
-        target_path: &PathBuf,
+        target_path: &Path,
     ) -> anyhow::Result<()> {
         fs::create_dir_all(target_path)?;
         for (_, dep) in self.dependencies.iter() {
-            let local_source = source_path.join(&dep.checksum);
-            let find_source = target_path.clone().join(&dep.name);
-            let inc_path = include_path.clone().join(&dep.name);
+            if self.skipped.contains(&dep.name) {
+                continue;
+            }
+
+            let local_source = source_path.join(hash::hex(&dep.checksum));
+            let find_source = target_path.join(&dep.name);
+            let inc_path = include_path.join(&dep.name);
             fs::create_dir_all(&find_source)?;
             fs::create_dir_all(&inc_path)?;
             copy_dir_all(&local_source, &find_source)?;
@@ -293,12 +887,311 @@ impl DependencyLock {
         Ok(())
     }
 
-    pub fn aggregate_target_properties(&self) -> anyhow::Result<LibraryTargetProperties> {
+    /// reports how the merge conflicts in the Lingo.lock were resolved
+    pub fn print_conflict_resolution(&self, updated: &DependencyLock) {
+        println!(
+            "{} merge conflicts in Lingo.lock, kept {} packages both sides agree on",
+            "Resolved".green().bold(),
+            self.dependencies.len()
+        );
+        self.print_changes(updated);
+    }
+
+    /// prints which packages were added, removed or changed their version or revision
+    /// compared to the `updated` lock
+    pub fn print_changes(&self, updated: &DependencyLock) {
+        let mut changed = false;
+
+        for (name, new) in updated.dependencies.iter() {
+            match self.dependencies.get(name) {
+                Some(old) if old.version == new.version && old.source.rev == new.source.rev => {}
+                Some(old) => {
+                    changed = true;
+                    println!(
+                        "{} {} {} -> {}",
+                        "Updating".green().bold(),
+                        name,
+                        old.display_version(),
+                        new.display_version()
+                    );
+                }
+                None => {
+                    changed = true;
+                    println!(
+                        "{} {} {}",
+                        "Adding".green().bold(),
+                        name,
+                        new.display_version()
+                    );
+                }
+            }
+        }
+
+        for (name, old) in self.dependencies.iter() {
+            if !updated.dependencies.contains_key(name) {
+                changed = true;
+                println!(
+                    "{} {} {}",
+                    "Removing".red().bold(),
+                    name,
+                    old.display_version()
+                );
+            }
+        }
+
+        if !changed {
+            println!(
+                "{} all dependencies are up to date",
+                "Unchanged".green().bold()
+            );
+        }
+    }
+
+    /// merges the target properties of the given packages and their dependencies
+    pub fn aggregate_target_properties(
+        &self,
+        roots: &[String],
+    ) -> anyhow::Result<LibraryTargetProperties> {
+        let reachable = self.reachable(roots);
         let mut i = LibraryTargetProperties::default();
         for tp in &self.loaded_dependencies {
-            i.merge(&tp.properties)?;
+            if reachable.contains(&tp.name) {
+                i.merge(&tp.properties.with_features(&tp.features)?)?;
+            }
         }
 
         Ok(i)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::management::package_checksum;
+    use crate::util::testing::{library_toml, write};
+    use crate::{DownloadError, GitCloneError};
+    use tempfile::tempdir;
+
+    #[test]
+    fn legacy_locks_are_migrated() {
+        // lock files of lingo 0.2.2 only know bare sha1 checksums
+        let legacy = DependencyLock::parse(
+            r#"[mqtt]
+name = "mqtt"
+version = "0.1.0"
+source = "git+https://example.org/mqtt.git#f372e81440da054de7444ee3ab16e6c1b3d4793c"
+checksum = "d4b6738bb4dc3d7a09a3e8ff5b0b4a4ffb3e2a18"
+"#,
+        )
+        .unwrap();
+        assert!(legacy.has_legacy_format());
+        assert!(legacy.has_legacy_checksums());
+
+        let mqtt = toml::from_str::<PackageDetails>(
+            "version = \">=0.1\"\ngit = \"https://example.org/mqtt.git\"\n",
+        )
+        .unwrap();
+        assert!(legacy
+            .check_up_to_date(&[("mqtt".to_string(), mqtt)], true)
+            .is_ok());
+
+        let file = LockFile {
+            version: LOCK_VERSION,
+            features: vec![],
+            packages: legacy.dependencies.values().cloned().collect(),
+        };
+        let text = toml::to_string(&file).unwrap();
+        assert!(text.starts_with("version = 2\n"));
+        assert!(text.contains("[[package]]\nname = \"mqtt\""));
+
+        let lock = DependencyLock::parse(&text).unwrap();
+        assert!(!lock.has_legacy_format());
+        assert_eq!(
+            lock.dependencies["mqtt"].checksum,
+            "d4b6738bb4dc3d7a09a3e8ff5b0b4a4ffb3e2a18"
+        );
+
+        let error = DependencyLock::parse("version = 3\n").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::LockFileTooNew(3, LOCK_VERSION))
+        ));
+        assert!(DependencyLock::parse("version = 2\n[[package]]\nname = 1\n").is_err());
+    }
+
+    #[test]
+    fn baseline_locks_are_upgraded() {
+        let root = tempdir().unwrap();
+        fs::create_dir_all(root.path().join("app")).unwrap();
+        write(root.path(), "lib/Lingo.toml", &library_toml("lib", "0.1.0"));
+        write(root.path(), "lib/src/lib/Lib.lf", "reactor Lib {}\n");
+
+        // written by lingo 0.2.2, the sha1 depended on the working directory of that run
+        let mut lock = DependencyLock::parse(
+            r#"[lib]
+name = "lib"
+version = "0.1.0"
+source = 'path+"../lib"'
+checksum = "7b767e8ecf821005a77312fad3b3ef2a6a34f91c"
+"#,
+        )
+        .unwrap();
+        assert!(lock.has_legacy_checksums());
+        assert_eq!(lock.dependencies["lib"].source.uri, "../lib");
+
+        let clone: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+        let target_path = root.path().join("app/build");
+        lock.init(
+            &target_path,
+            true,
+            None,
+            &VendorConfig::default(),
+            &clone,
+            &download,
+        )
+        .unwrap();
+
+        assert!(!lock.has_legacy_checksums());
+        assert_eq!(
+            lock.dependencies["lib"].checksum,
+            package_checksum(&target_path.join("lfc_include/lib")).unwrap()
+        );
+    }
+
+    #[test]
+    fn conflicts_keep_packages_both_sides_agree_on() {
+        let lock = DependencyLock::parse(
+            r#"version = 2
+
+[[package]]
+name = "motors"
+version = "0.1.0"
+source = "git+https://example.org/motors.git#f372e81440da054de7444ee3ab16e6c1b3d4793c"
+checksum = "sha256:01"
+
+[[package]]
+name = "mqtt"
+<<<<<<< HEAD
+version = "0.1.0"
+source = "git+https://example.org/mqtt.git#0123456789abcdef"
+checksum = "sha256:02"
+=======
+version = "0.2.0"
+source = "git+https://example.org/mqtt.git#fedcba9876543210"
+checksum = "sha256:03"
+>>>>>>> feature
+"#,
+        )
+        .unwrap();
+
+        assert!(lock.has_conflicts());
+        assert_eq!(lock.dependencies.keys().collect::<Vec<_>>(), ["motors"]);
+        assert_eq!(
+            lock.revisions()["motors"],
+            "f372e81440da054de7444ee3ab16e6c1b3d4793c"
+        );
+        assert!(lock.check_up_to_date(&[], false).is_err());
+    }
+
+    const TAGGED_LOCK: &str = r#"version = 2
+
+[[package]]
+name = "mqtt"
+version = "0.1.0"
+source = "git+https://example.org/mqtt.git?tag=v0.1.0#f372e81440da054de7444ee3ab16e6c1b3d4793c"
+checksum = "sha256:01"
+"#;
+
+    fn mqtt(details: &str) -> Vec<(String, PackageDetails)> {
+        let details = toml::from_str::<PackageDetails>(details).unwrap();
+        vec![("mqtt".to_string(), details)]
+    }
+
+    #[test]
+    fn changed_urls_outdate_the_lock() {
+        let lock = DependencyLock::parse(TAGGED_LOCK).unwrap();
+        assert!(lock
+            .check_up_to_date(
+                &mqtt("version = \">=0.1\"\ngit = \"https://example.org/mqtt.git\"\ntag = \"v0.1.0\"\n"),
+                true
+            )
+            .is_ok());
+
+        let error = lock
+            .check_up_to_date(
+                &mqtt("version = \">=0.1\"\ngit = \"https://example.org/fork/mqtt.git\"\ntag = \"v0.1.0\"\n"),
+                true,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::LockFileOutdated(_))
+        ));
+    }
+
+    #[test]
+    fn changed_tags_outdate_the_lock() {
+        let lock = DependencyLock::parse(TAGGED_LOCK).unwrap();
+        let source = &lock.dependencies["mqtt"].source;
+        assert_eq!(source.git_ref, Some(GitLock::Tag("v0.1.0".to_string())));
+        assert_eq!(source.uri, "https://example.org/mqtt.git");
+        assert!(toml::to_string(&lock.dependencies["mqtt"])
+            .unwrap()
+            .contains("mqtt.git?tag=v0.1.0#f372e81440da054de7444ee3ab16e6c1b3d4793c"));
+
+        let error = lock
+            .check_up_to_date(
+                &mqtt("version = \">=0.1\"\ngit = \"https://example.org/mqtt.git\"\ntag = \"v0.1.1\"\n"),
+                true,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::LockFileOutdated(_))
+        ));
+        assert!(lock
+            .check_up_to_date(
+                &mqtt("version = \">=0.1\"\ngit = \"https://example.org/mqtt.git\"\nbranch = \"main\"\n"),
+                true
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn only_reachable_packages_are_selected() {
+        let lock = DependencyLock::parse(
+            r#"version = 2
+
+[[package]]
+name = "motors"
+version = "0.1.0"
+source = "path+libs/motors"
+checksum = "sha256:01"
+dependencies = ["drivers"]
+
+[[package]]
+name = "drivers"
+version = "0.1.0"
+source = "path+libs/drivers"
+checksum = "sha256:02"
+dependencies = ["motors"]
+
+[[package]]
+name = "sensors"
+version = "0.1.0"
+source = "path+libs/sensors"
+checksum = "sha256:03"
+"#,
+        )
+        .unwrap();
+
+        let reachable = lock.reachable(&["motors".to_string(), "unknown".to_string()]);
+        assert_eq!(
+            reachable,
+            HashSet::from(["motors".to_string(), "drivers".to_string()])
+        );
+    }
+}
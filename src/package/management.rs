@@ -1,34 +1,142 @@
 use colored::Colorize;
-use log::error;
-use versions::{Requirement, Versioning};
+use log::{error, warn};
+use versions::{Op, Requirement, Versioning};
 
-use crate::util::sha1dir;
-use crate::{GitCloneAndCheckoutCap, GitUrl};
+use crate::util::archive::{self, ArchiveFormat};
+use crate::util::hash::{self, Algorithm};
+use crate::{DownloadCap, GitCloneAndCheckoutCap, GitCloneOptions, GitUrl};
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::env;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use tempfile::tempdir;
 use url::{ParseError, Url};
 use std::process::Command;
 
-use crate::package::lock::{PackageLockSource, PackageLockSourceType};
+use crate::package::cache::{CacheEntry, PackageCache};
+use crate::package::lock::{LockMode, PackageLock, PackageLockSource, PackageLockSourceType};
+use crate::package::patch::Patches;
+use crate::package::registry::{RegistryFile, RegistryIndex};
+use crate::package::resolver::{self, Candidate};
+use crate::package::vendor::{self, VendorConfig};
 use crate::package::{
+    features::{self, FeatureNode},
     lock::DependencyLock,
     target_properties::LibraryTargetProperties,
     tree::{DependencyTreeNode, GitLock, PackageDetails, ProjectSource},
-    ConfigFile, LIBRARY_DIRECTORY,
+    workspace::normalize,
+    Config, ConfigFile, LIBRARY_DIRECTORY,
 };
 use crate::util::errors::LingoError;
 
+/// package without its children together with the dependencies it declares
+type FetchedPackage = (DependencyTreeNode, Vec<(String, PackageDetails)>);
+
 #[derive(Default)]
 pub struct DependencyManager {
-    /// queue of packages that need processing
-    pulling_queue: Vec<(String, PackageDetails)>,
+    /// git revisions from the previous lock file that should be kept during a partial update
+    pinned_revisions: HashMap<String, String>,
     /// the flatten dependency tree with selected packages from the dependency tree
     lock: DependencyLock,
+    /// registry indices that were opened during the resolution
+    registries: HashMap<Url, RegistryIndex>,
+    /// user level cache fetched packages are taken from and added to
+    cache: Option<PackageCache>,
+    /// only packages from the cache or the local file system may be used
+    offline: bool,
+    /// packages that couldn't be fetched because of the offline mode
+    missing: Vec<String>,
+    /// packages fetched during the current resolution by name, source and requirement, packages
+    /// that are required more than once are only fetched once
+    fetched: HashMap<(String, String, String), Option<FetchedPackage>>,
+    /// optional dependencies as edges from the declaring package that enabled features use
+    enabled_optional: HashSet<(String, String)>,
+    /// vendored copies that are used instead of the package sources
+    vendored: VendorConfig,
+    /// versions from the previous lock file, yanked registry versions are only kept for those
+    locked_versions: HashMap<String, Versioning>,
+    /// archive checksums from the previous lock file, cached and downloaded tarballs of the
+    /// same source have to match them
+    locked_archives: HashMap<String, (PackageLockSource, String)>,
+    /// root dependencies the build doesn't need that keep the packages of the previous lock
+    kept_roots: Vec<String>,
+    /// locked packages of the kept root dependencies, they are locked again without fetching
+    kept: Vec<PackageLock>,
+    /// sources from the `[patch]` table of the root package
+    patches: Patches,
+    /// directory of the root package, relative path dependencies start there
+    root: PathBuf,
+}
+
+/// environment variable that enables the offline mode like `--offline`
+pub const OFFLINE_ENV_VARIABLE: &str = "LINGO_OFFLINE";
+
+/// checks if the offline mode is enabled through the environment
+pub fn offline_from_env() -> bool {
+    env::var(OFFLINE_ENV_VARIABLE)
+        .is_ok_and(|value| !value.is_empty() && value != "0" && value != "false")
+}
+
+/// Identifies sources that always yield the same content, only those are looked up in the
+/// package cache before fetching.
+fn cache_source(name: &str, package: &PackageDetails) -> Option<String> {
+    let options = PackageLockSource::from(package).options();
+
+    match (&package.mutual_exclusive, &package.git_tag) {
+        (ProjectSource::Git(url), Some(GitLock::Rev(rev))) => {
+            Some(format!("git+{url}{options}#{rev}"))
+        }
+        (ProjectSource::TarBall(url), _) if url.scheme() != "file" => {
+            Some(format!("tar+{url}{options}"))
+        }
+        (ProjectSource::Registry(Some(url)), _) if package.version.op == Op::Exact => package
+            .version
+            .version
+            .as_ref()
+            .map(|version| format!("registry+{url}#{name}@{version}")),
+        _ => None,
+    }
+}
+
+/// directory of the package the output directory `target_path` belongs to
+pub(crate) fn package_root(target_path: &Path) -> &Path {
+    match target_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// the Lingo.lock of the package the output directory `target_path` belongs to
+fn lock_file(target_path: &Path) -> PathBuf {
+    package_root(target_path).join("Lingo.lock")
+}
+
+/// checksum over the content of the package in `path`, honoring its exclude patterns
+pub fn package_checksum(path: &Path) -> anyhow::Result<String> {
+    let lingo_toml_text = fs::read_to_string(path.join("Lingo.toml"))?;
+    let config_file = toml::from_str::<ConfigFile>(&lingo_toml_text)?;
+
+    Ok(hash::dir_checksum(path, &config_file.package.exclude)?)
+}
+
+/// Checks the package `name` in `path` against the `expected` checksum and returns its
+/// sha256 checksum. Sha1 checksums of older versions of lingo can't be recomputed, they are
+/// accepted with a warning and replaced.
+pub fn verify_package_checksum(name: &str, path: &Path, expected: &str) -> anyhow::Result<String> {
+    let checksum = package_checksum(path)?;
+
+    if Algorithm::of(expected) == Algorithm::Sha1 {
+        warn!("cannot verify the legacy sha1 checksum of {name}, it is replaced by {checksum}");
+    } else if checksum != expected {
+        return Err(
+            LingoError::ChecksumMismatch(name.to_string(), expected.to_string(), checksum).into(),
+        );
+    }
+
+    Ok(checksum)
 }
 
 /// this copies all the files recursively from one location to another
@@ -56,28 +164,96 @@ impl TryFrom<&PackageLockSource> for PackageDetails {
             version: Default::default(),
             mutual_exclusive: match value.source_type {
                 PackageLockSourceType::REGISTRY => {
-                    todo!()
+                    ProjectSource::Registry(Some(Url::from_str(url)?))
                 }
                 PackageLockSourceType::GIT => ProjectSource::Git(Url::from_str(url)?),
                 PackageLockSourceType::TARBALL => ProjectSource::TarBall(Url::from_str(url)?),
                 PackageLockSourceType::PATH => ProjectSource::Path(PathBuf::from(url)),
             },
             git_tag: value.rev.clone().map(GitLock::Rev),
+            subdir: value.subdir.clone(),
+            submodules: value.submodules,
+            features: Default::default(),
             git_rev: value.rev.clone(),
+            archive_checksum: None,
+            locked_version: None,
         })
     }
 }
 
 impl PackageDetails {
-    /// this function fetches the specified location and places it at the given location
+    /// this function fetches the specified location and places it at the given location,
+    /// in `offline` mode only sources on the local file system are allowed
     pub fn fetch(
         &mut self,
+        name: &str,
+        library_path: &PathBuf,
+        offline: bool,
+        clone: &GitCloneAndCheckoutCap,
+        download: &DownloadCap,
+    ) -> anyhow::Result<()> {
+        let Some(subdir) = self.subdir.clone() else {
+            return self.fetch_source(name, library_path, offline, clone, download);
+        };
+
+        // only plain relative paths inside repositories and archives are allowed
+        let relative = subdir
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        let supported = matches!(
+            self.mutual_exclusive,
+            ProjectSource::Git(_) | ProjectSource::TarBall(_)
+        );
+        if !relative || !supported {
+            return Err(LingoError::InvalidSubdirectory(name.to_string(), subdir).into());
+        }
+
+        // the whole source is fetched, but only the subtree becomes the package
+        let checkout = tempdir()?;
+        self.fetch_source(
+            name,
+            &checkout.path().to_path_buf(),
+            offline,
+            clone,
+            download,
+        )?;
+
+        // symlinks inside the source must not lead out of it
+        let root = fs::canonicalize(checkout.path())?;
+        let package = fs::canonicalize(root.join(&subdir))
+            .ok()
+            .filter(|package| package.starts_with(&root) && package.join("Lingo.toml").exists());
+        let Some(package) = package else {
+            return Err(LingoError::InvalidSubdirectory(name.to_string(), subdir).into());
+        };
+
+        Ok(copy_dir_all(package, library_path)?)
+    }
+
+    fn fetch_source(
+        &mut self,
+        name: &str,
         library_path: &PathBuf,
+        offline: bool,
         clone: &GitCloneAndCheckoutCap,
+        download: &DownloadCap,
     ) -> anyhow::Result<()> {
+        let remote = match &self.mutual_exclusive {
+            ProjectSource::Git(url) => Some(url),
+            ProjectSource::TarBall(url) | ProjectSource::Registry(Some(url)) => {
+                Some(url).filter(|url| url.scheme() != "file")
+            }
+            ProjectSource::Path(_) | ProjectSource::Registry(None) => None,
+        };
+
+        if let (true, Some(url)) = (offline, remote) {
+            return Err(LingoError::NetworkAccessOffline(url.to_string()).into());
+        }
+
         match &self.mutual_exclusive {
             ProjectSource::Path(path_buf) => {
                 let src = fs::canonicalize(path_buf)?;
+                fs::create_dir_all(library_path)?;
                 let dst = fs::canonicalize(library_path)?;
                 Ok(copy_dir_all(src, dst)?)
             }
@@ -86,19 +262,79 @@ impl PackageDetails {
                     GitUrl::from(git_url.as_str()),
                     library_path,
                     self.git_tag.clone(),
+                    GitCloneOptions {
+                        submodules: self.submodules,
+                        files_only: true,
+                    },
                 )?;
                 Ok(())
             }
-            _ => Ok(()),
+            ProjectSource::TarBall(url) => {
+                let format = ArchiveFormat::from_name(url.path())
+                    .ok_or(LingoError::UnsupportedArchiveFormat(url.to_string()))?;
+
+                // remote archives are downloaded into a temporary folder first
+                let download_dir = tempdir()?;
+                let archive = if url.scheme() == "file" {
+                    url.to_file_path().map_err(|_| {
+                        io::Error::new(ErrorKind::InvalidInput, format!("invalid file url {url}"))
+                    })?
+                } else {
+                    let archive = download_dir.path().join("archive");
+                    download(url, &archive)?;
+                    archive
+                };
+
+                // archives locked by older versions of lingo are verified with sha1
+                if let Some(expected) = &self.archive_checksum {
+                    let checksum = hash::file_checksum(&archive, Algorithm::of(expected))?;
+                    if expected != &checksum {
+                        return Err(LingoError::ArchiveChecksumMismatch(
+                            url.to_string(),
+                            expected.clone(),
+                            checksum,
+                        )
+                        .into());
+                    }
+                }
+
+                archive::extract(&archive, format, library_path)?;
+                self.archive_checksum = Some(hash::file_checksum(&archive, Algorithm::Sha256)?);
+                Ok(())
+            }
+            ProjectSource::Registry(None) => {
+                Err(LingoError::NoRegistryConfigured(name.to_string()).into())
+            }
+            ProjectSource::Registry(Some(index_url)) => {
+                let index = RegistryIndex::open(index_url, clone)?;
+                let entry = index.select(name, &self.version, self.locked_version.as_ref())?;
+
+                // the registry only tells us where the package lives
+                let mut details = PackageDetails::try_from(&entry.source)?;
+                details.archive_checksum = entry.archive_checksum.clone();
+                details.fetch(name, library_path, offline, clone, download)?;
+
+                verify_package_checksum(name, library_path, &entry.checksum)?;
+                Ok(())
+            }
         }
     }
 }
 
-fn get_untracked_dirs() -> Vec<String> {
+/// checks if the error was caused by a fetch that needed the network in offline mode
+pub(crate) fn is_network_access_offline(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<LingoError>(),
+        Some(LingoError::NetworkAccessOffline(_))
+    )
+}
+
+fn get_untracked_dirs(root: &Path) -> Vec<String> {
     let output = Command::new("git")
         .arg("ls-files")
         .arg("--others")
         .arg("--exclude-standard")
+        .current_dir(root)
         .output()
         .expect("Failed to run git ls-files");
 
@@ -106,16 +342,10 @@ fn get_untracked_dirs() -> Vec<String> {
 
     let mut untracked_dirs = HashSet::new();
 
-    let untracked_dirs_iter = stdout
-        .lines()
-        .filter_map(|line| {
-            let path = line.trim();
-            if let Some(pos) = path.rfind('/') {
-                Some(path[..pos].to_string())
-            } else {
-                None
-            }
-        });
+    let untracked_dirs_iter = stdout.lines().filter_map(|line| {
+        let path = line.trim();
+        path.rfind('/').map(|pos| path[..pos].to_string())
+    });
 
     for dir in untracked_dirs_iter {
         untracked_dirs.insert(dir);
@@ -124,22 +354,62 @@ fn get_untracked_dirs() -> Vec<String> {
     untracked_dirs.into_iter().collect()
 }
 
+/// `[features]` tables and declared dependencies of the root package and the given packages
+fn feature_nodes<'a>(
+    root: &'a Config,
+    dependencies: &'a [(String, PackageDetails)],
+    nodes: impl IntoIterator<Item = &'a DependencyTreeNode>,
+) -> HashMap<String, FeatureNode<'a>> {
+    let mut packages = HashMap::from([(
+        root.package.name.clone(),
+        FeatureNode {
+            table: &root.features,
+            dependencies,
+        },
+    )]);
+    for node in nodes {
+        packages.insert(
+            node.name.clone(),
+            FeatureNode {
+                table: &node.feature_table,
+                dependencies: &node.declared,
+            },
+        );
+    }
+    packages
+}
+
 impl DependencyManager {
-    pub fn cleanup(target_path: &Path)  -> anyhow::Result<DependencyManager> {
+    fn new(
+        root: &Path,
+        offline: bool,
+        vendored: VendorConfig,
+        patches: Patches,
+    ) -> DependencyManager {
+        DependencyManager {
+            cache: PackageCache::open(),
+            offline,
+            vendored,
+            patches,
+            root: root.to_path_buf(),
+            ..Default::default()
+        }
+    }
 
+    /// removes the Lingo.lock of the package in `root` and the untracked package copies next to it
+    pub fn cleanup(root: &Path) -> anyhow::Result<DependencyManager> {
         let result = DependencyManager::default();
         let lock_ref: DependencyLock;
-        let lock_file = target_path.join("Lingo.lock");
+        let lock_file = root.join("Lingo.lock");
 
         if lock_file.exists() {
-            lock_ref = toml::from_str::<DependencyLock>(&fs::read_to_string(lock_file.clone())?)
-                .expect("cannot parse lock");
+            lock_ref = DependencyLock::read(&lock_file)?;
 
-            let untracked_dirs = get_untracked_dirs();
+            let untracked_dirs = get_untracked_dirs(root);
             log::info!("untracked_dirs:{:?}", untracked_dirs);
 
             for (_, lock) in lock_ref.dependencies.iter() {
-                let package_path = target_path.join(&lock.name);
+                let package_path = root.join(&lock.name);
 
                 if untracked_dirs.contains(&lock.name) {
                     log::info!("Removing untracked directory: {}", lock.name);
@@ -151,111 +421,568 @@ impl DependencyManager {
             }
             fs::remove_file(lock_file.clone()).expect("Failed to remove Lingo.lock");
         }
-        return Ok(result);
+        Ok(result)
     }
 
+    /// Loads the dependencies from the Lingo.lock if it matches the dependencies of the `root`
+    /// package, otherwise they are resolved again unless the lock mode forbids changing the
+    /// lock. Only the given `packages` and their dependencies are fetched, other dependencies
+    /// that are still locked keep their packages when resolving again. The `[patch]` table of
+    /// the `root` package applies to the whole dependency graph.
     pub fn from_dependencies(
-        dependencies: Vec<(String, PackageDetails)>,
+        root: &Config,
+        packages: &[String],
         target_path: &Path,
+        mode: LockMode,
+        offline: bool,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
     ) -> anyhow::Result<DependencyManager> {
-        // create library folder
-        let library_path = target_path.join(LIBRARY_DIRECTORY);
-        fs::create_dir_all(&library_path)?;
-
-        let mut manager;
-        let mut lock: DependencyLock;
-        let lock_file = target_path.join("../Lingo.lock");
+        let lock_file = lock_file(target_path);
+        let offline = offline || mode == LockMode::Frozen;
+        let vendored = VendorConfig::load(package_root(target_path))?;
+        let mut dependencies = root.all_dependencies()?;
+        root.patches.apply_all(&mut dependencies);
 
         // checks if a Lingo.lock file exists
-        if lock_file.exists() {
+        let mut previous_lock = None;
+        let up_to_date = if lock_file.exists() {
             // reads and parses Lockfile
-            lock = toml::from_str::<DependencyLock>(&fs::read_to_string(lock_file)?)
-                .expect("cannot parse lock");
+            let lock = DependencyLock::read(&lock_file)?;
 
-            // if a lock file is present it will load the dependencies from it and checks
-            // integrity of the build directory
-            if let Ok(()) = lock.init(&target_path.join("lfc_include"), git_clone_and_checkout_cap)
+            match lock
+                .check_up_to_date(&dependencies, mode != LockMode::Update)
+                .and_then(|()| root.patches.check_lock(&lock))
+                .and_then(|()| lock.check_features(&root.requested_features))
             {
+                Ok(()) => Ok(lock),
+                Err(e) => {
+                    previous_lock = Some(lock);
+                    Err(e)
+                }
+            }
+        } else {
+            Err(LingoError::LockFileOutdated("there is no Lingo.lock".to_string()).into())
+        };
+
+        match up_to_date {
+            Ok(mut lock) => {
+                let legacy = lock.has_legacy_checksums() || lock.has_legacy_format();
+                let cache = PackageCache::open();
+                lock.skip_unreachable(packages);
+
+                // loads the dependencies from the lock and checks the integrity of the build directory
+                lock.init(
+                    target_path,
+                    offline,
+                    cache.as_ref(),
+                    &vendored,
+                    git_clone_and_checkout_cap,
+                    download_cap,
+                )?;
+
+                // sha1 checksums and old formats are upgraded unless the lock must not change
+                if legacy && mode == LockMode::Update {
+                    lock.write(&lock_file)?;
+                }
+
                 return Ok(DependencyManager {
-                    pulling_queue: vec![],
                     lock,
+                    cache,
+                    offline,
+                    vendored,
+                    root: package_root(target_path).to_path_buf(),
+                    ..Default::default()
                 });
             }
+            Err(e) if mode != LockMode::Update => return Err(e),
+            Err(_) => {}
         }
 
         // creates a new dependency manager object
-        manager = DependencyManager::default();
+        let mut manager = DependencyManager::new(
+            package_root(target_path),
+            offline,
+            vendored,
+            root.patches.clone(),
+        );
+        if let Some(previous_lock) = &previous_lock {
+            manager.locked_versions = previous_lock.versions();
+            manager.locked_archives = previous_lock.archive_checksums();
+
+            // dependencies the build doesn't reach, like dev-dependencies, stay locked as they are
+            if !previous_lock.has_conflicts()
+                && previous_lock
+                    .check_features(&root.requested_features)
+                    .and_then(|()| root.patches.check_lock(previous_lock))
+                    .is_ok()
+            {
+                let unreachable: Vec<(String, PackageDetails)> = dependencies
+                    .iter()
+                    .filter(|(name, _)| !packages.contains(name))
+                    .cloned()
+                    .collect();
+                let mut kept_roots: Vec<String> = unreachable
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .filter(|name| {
+                        let declared: Vec<_> = unreachable
+                            .iter()
+                            .filter(|(dependency, _)| dependency == name)
+                            .cloned()
+                            .collect();
+                        previous_lock.check_up_to_date(&declared, false).is_ok()
+                    })
+                    .collect();
+                kept_roots.dedup();
+
+                manager.kept = previous_lock
+                    .reachable(&kept_roots)
+                    .into_iter()
+                    .map(|name| previous_lock.dependencies[&name].clone())
+                    .collect();
+                manager.kept_roots = kept_roots;
+            }
+
+            // packages both sides of a merge conflict agree on stay at their revision
+            if previous_lock.has_conflicts() {
+                manager.pinned_revisions = previous_lock.revisions();
+            }
+        }
+
+        manager.resolve(
+            root,
+            dependencies,
+            target_path,
+            git_clone_and_checkout_cap,
+            download_cap,
+        )?;
+
+        if let Some(previous_lock) = previous_lock.filter(DependencyLock::has_conflicts) {
+            previous_lock.print_conflict_resolution(&manager.lock);
+        }
+
+        Ok(manager)
+    }
 
-        // starts recursively pulling dependencies
-        let root_nodes = manager.pull(
-            dependencies.clone(),
+    /// Re-resolves the given dependencies while ignoring the existing Lingo.lock. If `packages`
+    /// is not empty only those packages are refreshed, all other git dependencies are kept
+    /// at the revision recorded in the previous lock file.
+    pub fn update(
+        root: &Config,
+        mut dependencies: Vec<(String, PackageDetails)>,
+        target_path: &Path,
+        packages: &[String],
+        offline: bool,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<DependencyManager> {
+        let lock_file = lock_file(target_path);
+
+        let previous_lock = if lock_file.exists() {
+            Some(DependencyLock::read(&lock_file)?)
+        } else {
+            None
+        };
+
+        let unknown_names = packages
+            .iter()
+            .filter(|&name| {
+                !dependencies
+                    .iter()
+                    .any(|(dependency, _)| dependency == name)
+                    && !previous_lock
+                        .as_ref()
+                        .is_some_and(|lock| lock.dependencies.contains_key(name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !unknown_names.is_empty() {
+            return Err(LingoError::UnknownDependencyNames(unknown_names).into());
+        }
+
+        let vendored = VendorConfig::load(package_root(target_path))?;
+        let mut manager = DependencyManager::new(
+            package_root(target_path),
+            offline,
+            vendored,
+            root.patches.clone(),
+        );
+        root.patches.apply_all(&mut dependencies);
+
+        if let Some(previous_lock) = &previous_lock {
+            if !packages.is_empty() {
+                manager.locked_versions = previous_lock.versions();
+                manager
+                    .locked_versions
+                    .retain(|name, _| !packages.contains(name));
+
+                manager.pinned_revisions = previous_lock.revisions();
+                manager
+                    .pinned_revisions
+                    .retain(|name, _| !packages.contains(name));
+
+                manager.locked_archives = previous_lock.archive_checksums();
+                manager
+                    .locked_archives
+                    .retain(|name, _| !packages.contains(name));
+            }
+        }
+
+        // the include folder is rebuilt from scratch so no stale files survive the update
+        let include_folder = target_path.join("lfc_include");
+        if include_folder.exists() {
+            fs::remove_dir_all(&include_folder)?;
+        }
+
+        manager.resolve(
+            root,
+            dependencies,
             target_path,
             git_clone_and_checkout_cap,
+            download_cap,
         )?;
 
+        match &previous_lock {
+            Some(previous_lock) if previous_lock.has_conflicts() => {
+                previous_lock.print_conflict_resolution(&manager.lock)
+            }
+            Some(previous_lock) => previous_lock.print_changes(&manager.lock),
+            None => DependencyLock::default().print_changes(&manager.lock),
+        }
+
+        Ok(manager)
+    }
+
+    /// Makes sure the dependencies are locked and fetched and copies them into `directory`
+    /// inside the package, future builds take the packages from there.
+    pub fn vendor(
+        root: &Config,
+        dependencies: Vec<(String, PackageDetails)>,
+        target_path: &Path,
+        directory: &Path,
+        offline: bool,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<DependencyManager> {
+        let packages = dependencies
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        let mut manager = DependencyManager::from_dependencies(
+            root,
+            &packages,
+            target_path,
+            LockMode::Update,
+            offline,
+            git_clone_and_checkout_cap,
+            download_cap,
+        )?;
+
+        manager.vendored = vendor::vendor(
+            &manager.lock,
+            &target_path.join("lfc_include"),
+            package_root(target_path),
+            directory,
+        )?;
+
+        for package in &manager.vendored.packages {
+            println!(
+                "{} {} v{} to {}",
+                "Vendoring".green().bold(),
+                package.name,
+                package.version,
+                package.path.display()
+            );
+        }
+
+        Ok(manager)
+    }
+
+    /// Resolves the `[dependencies]` of the `root` package into the output directory
+    /// `target_path` like a package that depends on it would. No lock, vendored copies or
+    /// cached packages are used and the `[patch]` table of `root` is ignored.
+    pub(crate) fn isolated(
+        root: &Config,
+        target_path: &Path,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<DependencyManager> {
+        let mut manager = DependencyManager {
+            root: package_root(target_path).to_path_buf(),
+            ..Default::default()
+        };
+        let dependencies = root.dependencies.clone().into_iter().collect();
+
+        manager.resolve(
+            root,
+            dependencies,
+            target_path,
+            git_clone_and_checkout_cap,
+            download_cap,
+        )?;
+
+        Ok(manager)
+    }
+
+    /// Pulls all dependencies, makes the package selection and writes the Lingo.lock. The
+    /// requested features of the `root` package decide which optional packages are used.
+    fn resolve(
+        &mut self,
+        root: &Config,
+        dependencies: Vec<(String, PackageDetails)>,
+        target_path: &Path,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<()> {
+        let library_path = target_path.join(LIBRARY_DIRECTORY);
+        fs::create_dir_all(&library_path)?;
+        let root_name = &root.package.name;
+
+        // root dependencies that keep their locked packages are not pulled
+        let kept = std::mem::take(&mut self.kept);
+        let kept_roots = std::mem::take(&mut self.kept_roots);
+        let pulled: Vec<(String, PackageDetails)> = dependencies
+            .iter()
+            .filter(|(name, _)| !kept_roots.contains(name))
+            .cloned()
+            .collect();
+
+        // optional dependencies are only pulled once an enabled feature uses them, the features
+        // are unified again until they don't enable any further optional dependency
+        self.fetched.clear();
+        self.enabled_optional.clear();
+        let (required, root_nodes) = loop {
+            let required: Vec<(String, PackageDetails)> = pulled
+                .iter()
+                .filter(|(name, details)| {
+                    !details.features.optional
+                        || self
+                            .enabled_optional
+                            .contains(&(root_name.clone(), name.clone()))
+                })
+                .cloned()
+                .collect();
+
+            // starts recursively pulling dependencies
+            let root_nodes = self.pull(
+                required.clone(),
+                target_path,
+                git_clone_and_checkout_cap,
+                download_cap,
+            )?;
+
+            let mut walked = vec![];
+            let mut walk: Vec<&DependencyTreeNode> = root_nodes.iter().collect();
+            while let Some(node) = walk.pop() {
+                walk.extend(node.dependencies.iter());
+                walked.push(node);
+            }
+
+            let packages = feature_nodes(root, &pulled, walked);
+            let unified = features::unify(root_name, &root.requested_features, &packages)?;
+            let enabled: Vec<(String, String)> = unified
+                .edges
+                .into_iter()
+                .filter(|edge| !self.enabled_optional.contains(edge))
+                .filter(|(package, dependency)| {
+                    packages[package]
+                        .dependencies
+                        .iter()
+                        .any(|(name, details)| name == dependency && details.features.optional)
+                })
+                .collect();
+
+            if enabled.is_empty() {
+                break (required, root_nodes);
+            }
+            self.enabled_optional.extend(enabled);
+        };
+
         // flattens the dependency tree and makes the package selection
-        let selection = DependencyManager::flatten(root_nodes.clone())?;
+        let selection = self.flatten(
+            root_name,
+            &required,
+            root_nodes,
+            &library_path,
+            git_clone_and_checkout_cap,
+            download_cap,
+        )?;
 
-        // creates a lock file struct from the selected packages
-        lock = DependencyLock::create(selection);
+        // optional packages that no enabled feature asks for are dropped from the selection
+        let packages = feature_nodes(root, &pulled, &selection);
+        let unified = features::unify(root_name, &root.requested_features, &packages)?;
 
-        // writes the lock file down
-        let mut lock_file = File::create(target_path.join("../Lingo.lock"))?;
+        let selection = selection
+            .into_iter()
+            .filter(|node| unified.features.contains_key(&node.name))
+            .map(|mut node| {
+                node.features = unified.of(&node.name);
+                node.dependencies.retain(|child| {
+                    unified
+                        .edges
+                        .contains(&(node.name.clone(), child.name.clone()))
+                });
+                node
+            })
+            .collect();
 
-        let serialized_toml = toml::to_string(&lock).expect("cannot generate toml");
+        // creates a lock file struct from the selected packages
+        let mut lock = DependencyLock::create(selection, &root.requested_features);
+
+        // the kept packages are resolved again if the build needs others of their dependencies
+        if !lock.keep(kept) {
+            return self.resolve(
+                root,
+                dependencies,
+                target_path,
+                git_clone_and_checkout_cap,
+                download_cap,
+            );
+        }
 
-        lock_file.write_all(serialized_toml.as_ref())?;
+        for name in self.patches.names() {
+            if !lock.dependencies.contains_key(name) {
+                warn!("patch for {name} was not used, no package depends on it");
+            }
+        }
+
+        // writes the lock file down
+        lock.write(&lock_file(target_path))?;
 
         // moves the selected packages into the include folder
         let include_folder = target_path.join("lfc_include");
-        let include_path = &PathBuf::from(".");
-        lock.create_library_folder(&include_path,&library_path, &include_folder)
-            .expect("creating lock folder failed");
+        lock.create_library_folder(package_root(target_path), &library_path, &include_folder)?;
 
         // saves the lockfile with the dependency manager
-        manager.lock = lock;
+        self.lock = lock;
 
-        Ok(manager)
+        Ok(())
     }
 
     pub fn pull(
         &mut self,
-        mut dependencies: Vec<(String, PackageDetails)>,
+        dependencies: Vec<(String, PackageDetails)>,
         root_path: &Path,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
     ) -> anyhow::Result<Vec<DependencyTreeNode>> {
-        let mut sub_dependencies = vec![];
-        self.pulling_queue.append(&mut dependencies);
+        let mut root_nodes = vec![];
         let sub_dependency_path = root_path.join("libraries");
-        //fs::remove_dir_all(&sub_dependency_path)?;
         fs::create_dir_all(&sub_dependency_path)?;
 
-        while !self.pulling_queue.is_empty() {
-            if let Some((package_name, package_details)) = self.pulling_queue.pop() {
-                print!("{} {} ...", "Cloning".green().bold(), package_name);
-                let node = match self.non_recursive_fetching(
-                    &package_name,
-                    package_details,
-                    &sub_dependency_path,
+        self.missing.clear();
+
+        for (package_name, package_details) in dependencies {
+            let node = self.pull_package(
+                &package_name,
+                package_details,
+                &sub_dependency_path,
+                &mut vec![],
+                git_clone_and_checkout_cap,
+                download_cap,
+            )?;
+
+            root_nodes.extend(node);
+        }
+
+        if !self.missing.is_empty() {
+            let missing = std::mem::take(&mut self.missing);
+            return Err(LingoError::PackagesNotAvailableOffline(missing).into());
+        }
+
+        Ok(root_nodes)
+    }
+
+    /// fetches the package and attaches all of its dependencies as children,
+    /// `ancestors` are the packages on the path from the root to this package.
+    /// In offline mode packages that would need network access are collected in `missing`.
+    fn pull_package(
+        &mut self,
+        name: &str,
+        package: PackageDetails,
+        base_path: &Path,
+        ancestors: &mut Vec<String>,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<Option<DependencyTreeNode>> {
+        if let Some(position) = ancestors.iter().position(|ancestor| ancestor == name) {
+            let mut cycle = ancestors[position..].to_vec();
+            cycle.push(name.to_string());
+            return Err(LingoError::DependencyCycle(cycle).into());
+        }
+
+        // patched packages are fetched from their patched source wherever they are required
+        let mut package = package;
+        self.patches.apply(name, &mut package);
+
+        let source = PackageLockSource::from(&package);
+        let key = (
+            name.to_string(),
+            format!(
+                "{}+{}{}{:?}",
+                source.source_type,
+                source.uri,
+                source.options(),
+                package.git_tag
+            ),
+            package.version.to_string(),
+        );
+        let fetched = match self.fetched.get(&key) {
+            Some(fetched) => fetched.clone(),
+            None => {
+                print!("{} {} ...", "Cloning".green().bold(), name);
+                let fetched = match self.non_recursive_fetching(
+                    name,
+                    package,
+                    base_path,
                     git_clone_and_checkout_cap,
+                    download_cap,
                 ) {
-                    Ok(value) => value,
-                    Err(e) => {
-                        return Err(e);
+                    Ok(value) => Some(value),
+                    Err(e) if is_network_access_offline(&e) => {
+                        println!(" {}", "not available offline".red());
+                        None
                     }
+                    Err(e) => return Err(e),
                 };
+                self.fetched.insert(key, fetched.clone());
+                fetched
+            }
+        };
 
-                // log::info!("NODE:{:?}", node);
+        let Some((mut node, children)) = fetched else {
+            if !self.missing.iter().any(|missing| missing == name) {
+                self.missing.push(name.to_string());
+            }
+            return Ok(None);
+        };
 
-                sub_dependencies.push(node);
-            } else {
-                break;
+        ancestors.push(name.to_string());
+        for (child_name, child_details) in children {
+            // optional dependencies are only pulled once an enabled feature uses them
+            if child_details.features.optional
+                && !self
+                    .enabled_optional
+                    .contains(&(name.to_string(), child_name.clone()))
+            {
+                continue;
             }
+
+            let child = self.pull_package(
+                &child_name,
+                child_details,
+                base_path,
+                ancestors,
+                git_clone_and_checkout_cap,
+                download_cap,
+            )?;
+            node.dependencies.extend(child);
         }
+        ancestors.pop();
 
-        //dependencies
-        Ok(sub_dependencies)
+        Ok(Some(node))
     }
 
     pub(crate) fn non_recursive_fetching(
@@ -264,7 +991,8 @@ impl DependencyManager {
         mut package: PackageDetails,
         base_path: &Path,
         git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
-    ) -> anyhow::Result<DependencyTreeNode> {
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<(DependencyTreeNode, Vec<(String, PackageDetails)>)> {
         // creating the directory where the library will be housed
         let library_path = base_path; //.join("libs");
                                       // place where to drop the source
@@ -278,14 +1006,98 @@ impl DependencyManager {
         fs::create_dir_all(library_path)?;
         fs::create_dir_all(&temporary_path)?;
 
-        // cloning the specified package
-        package.fetch(&temporary_path, git_clone_and_checkout_cap)?;
+        // during a partial update packages that are not updated stay at their locked revision,
+        // the lock keeps the reference they were declared with
+        let declared_ref = package.git_tag.clone();
+        if let (Some(rev), ProjectSource::Git(_)) =
+            (self.pinned_revisions.get(name), &package.mutual_exclusive)
+        {
+            package.git_tag = Some(GitLock::Rev(rev.clone()));
+        }
 
-        let hash = sha1dir::checksum_current_dir(&temporary_path, false);
-        let include_path = library_path.join(hash.to_string());
+        let locked_archive = self
+            .locked_archives
+            .get(name)
+            .filter(|(source, _)| source == &PackageLockSource::from(&package))
+            .map(|(_, checksum)| checksum.clone());
+
+        // vendored copies take precedence, packages from immutable sources are taken from the
+        // cache if possible
+        let source = cache_source(name, &package);
+        let vendored = self.vendored.replacement(name, &package).cloned();
+        let cached = match (&self.cache, &source, &vendored) {
+            (_, _, Some(vendored)) => {
+                copy_dir_all(self.vendored.path(vendored), &temporary_path)?;
+                Some(CacheEntry {
+                    name: vendored.name.clone(),
+                    version: vendored.version.clone(),
+                    checksum: vendored.checksum.clone(),
+                    source: None,
+                    rev: vendored.source.rev.clone(),
+                    archive_checksum: vendored.archive_checksum.clone(),
+                })
+            }
+            (Some(cache), Some(source), None) => cache
+                .restore_source(source, &temporary_path)
+                .unwrap_or_else(|e| {
+                    warn!("ignoring package cache: {e}");
+                    None
+                })
+                .filter(|entry| {
+                    let matches =
+                        locked_archive.is_none() || entry.archive_checksum == locked_archive;
+                    if !matches {
+                        warn!("ignoring cached {name}, its archive differs from the locked one");
+                    }
+                    matches
+                }),
+            _ => None,
+        };
+
+        match &cached {
+            Some(entry) => {
+                package.git_rev = entry.rev.clone();
+                package.archive_checksum = entry.archive_checksum.clone();
+            }
+            // cloning the specified package
+            None => {
+                // a rejected cache entry may have left its files behind
+                fs::remove_dir_all(&temporary_path)?;
+                fs::create_dir_all(&temporary_path)?;
+
+                let mut anchored = package.clone();
+                anchored.anchor(&self.root);
+                anchored.locked_version = self.locked_versions.get(name).cloned();
+                anchored.archive_checksum = locked_archive;
+                anchored.fetch(
+                    name,
+                    &temporary_path,
+                    self.offline,
+                    git_clone_and_checkout_cap,
+                    download_cap,
+                )?;
+
+                package.git_rev = anchored.git_rev;
+                package.archive_checksum = anchored.archive_checksum;
+            }
+        }
+
+        package.git_tag = declared_ref;
+
+        let hash = package_checksum(&temporary_path)?;
+        let include_path = library_path.join(hash::hex(&hash));
 
         let lingo_toml_text = fs::read_to_string(temporary_path.clone().join("Lingo.toml"))?;
-        let read_toml = toml::from_str::<ConfigFile>(&lingo_toml_text)?.to_config(&temporary_path);
+        let mut config_file = toml::from_str::<ConfigFile>(&lingo_toml_text)?;
+
+        // dependencies of registry packages are looked up in the same registry
+        if let ProjectSource::Registry(Some(index)) = &package.mutual_exclusive {
+            config_file.registry = Some(RegistryFile {
+                index: index.clone(),
+            });
+        }
+
+        let read_toml = config_file.to_config(&temporary_path);
 
         println!(" {}", read_toml.package.version);
 
@@ -310,128 +1122,541 @@ impl DependencyManager {
             .into());
         }
 
-        let dependencies = vec![];
-
-        for dep in read_toml.dependencies {
-            self.pulling_queue.push(dep);
+        if let (Some(cache), None) = (&self.cache, &cached) {
+            let entry = CacheEntry {
+                name: name.to_string(),
+                version: read_toml.package.version.clone(),
+                checksum: hash.clone(),
+                source,
+                rev: package.git_rev.clone(),
+                archive_checksum: package.archive_checksum.clone(),
+            };
+
+            if let Err(e) = cache.insert(&entry, &temporary_path) {
+                warn!("cannot add {name} to the package cache: {e}");
+            }
         }
 
         fs::create_dir_all(&include_path)?;
         copy_dir_all(&temporary_path, &include_path)?;
 
-        Ok(DependencyTreeNode {
+        let mut children: Vec<(String, PackageDetails)> =
+            read_toml.dependencies.into_iter().collect();
+        children.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // relative path dependencies of a path package are relative to that package
+        if let ProjectSource::Path(directory) = &package.mutual_exclusive {
+            for (_, child) in &mut children {
+                if let ProjectSource::Path(path) = &mut child.mutual_exclusive {
+                    if path.is_relative() {
+                        *path = normalize(&directory.join(&*path));
+                    }
+                }
+            }
+        }
+
+        let node = DependencyTreeNode {
             name: name.to_string(),
             package: package.clone(),
             location: include_path.clone(),
-            include_path: config.location.clone(),
-            dependencies: dependencies.clone(),
-            hash: hash.to_string(),
+            include_path: config
+                .location
+                .strip_prefix(&temporary_path)
+                .unwrap_or(&config.location)
+                .to_path_buf(),
+            // children are attached by the caller once they are fetched
+            dependencies: vec![],
+            hash,
             version: read_toml.package.version.clone(),
             properties: config.properties,
-        })
+            feature_table: read_toml.features,
+            declared: children.clone(),
+            // features are enabled once the whole graph is known
+            features: vec![],
+        };
+
+        Ok((node, children))
     }
 
-    fn flatten(root_nodes: Vec<DependencyTreeNode>) -> anyhow::Result<Vec<DependencyTreeNode>> {
-        // implementation idea:
-        // 1.   we collect all the version requirements for packages => are the different
-        //      constraints satisfiable ?
-        // 2.   we collect all the different sources
-        // 3.   finding the set of sources that satisfies the set of version constraints
-        // 4.   pick the newest version from that set
-        // TODO: later we can probably do this in one pass
+    /// opens the registry index once and keeps it around for the rest of the resolution
+    fn registry(
+        &mut self,
+        url: &Url,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+    ) -> anyhow::Result<&RegistryIndex> {
+        if !self.registries.contains_key(url) {
+            if self.offline && url.scheme() != "file" {
+                return Err(LingoError::NetworkAccessOffline(url.to_string()).into());
+            }
+
+            let index = RegistryIndex::open(url, git_clone_and_checkout_cap)?;
+            self.registries.insert(url.clone(), index);
+        }
+
+        Ok(&self.registries[url])
+    }
+
+    /// Makes the package selection. Every pulled package and every version published in a
+    /// registry is a candidate, the resolver then picks the newest set of versions that
+    /// satisfies all requirements. Selected versions that are only known from a registry
+    /// index are fetched afterwards.
+    fn flatten(
+        &mut self,
+        root: &str,
+        dependencies: &[(String, PackageDetails)],
+        root_nodes: Vec<DependencyTreeNode>,
+        base_path: &Path,
+        git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+        download_cap: &DownloadCap,
+    ) -> anyhow::Result<Vec<DependencyTreeNode>> {
+        let mut candidates = HashMap::<String, Vec<Candidate>>::new();
+
+        // the requirements of every pulled version are taken from its edges in the tree
+        let mut walk: Vec<&DependencyTreeNode> = root_nodes.iter().collect();
+        while let Some(node) = walk.pop() {
+            walk.extend(node.dependencies.iter());
+
+            let known = candidates.entry(node.name.clone()).or_default();
+            if known.iter().any(|known| known.version == node.version) {
+                continue;
+            }
 
-        let mut constraints = HashMap::<&String, Vec<Requirement>>::new();
-        let mut sources = HashMap::<&String, Vec<&DependencyTreeNode>>::new();
+            known.push(Candidate {
+                version: node.version.clone(),
+                dependencies: node.requirements(),
+            });
+        }
 
-        // this basically flattens the
         let mut nodes = Vec::new();
         for node in root_nodes {
             let mut children = node.aggregate();
             nodes.append(&mut children);
         }
 
-        for node in &nodes {
-            let constraint = &node.package.version;
+        // registry packages that haven't been fetched yet, with the index they come from
+        let mut registry_candidates = HashMap::<(String, String), Url>::new();
 
-            constraints
-                .entry(&node.name)
-                .and_modify(|value| {
-                    value.push(constraint.clone());
-                })
-                .or_insert(vec![constraint.clone()]);
-
-            sources
-                .entry(&node.name)
-                .and_modify(move |value| {
-                    value.push(node);
-                })
-                .or_insert(vec![&node]);
-        }
-
-        let merged: Vec<(&String, Vec<Requirement>, Vec<&DependencyTreeNode>)> = constraints
-            .into_iter()
-            .filter_map(move |(key, requirements)| {
-                sources
-                    .get_mut(&key)
-                    .map(move |location| (key, requirements, location.clone()))
+        let mut registry_queue: Vec<(String, Url)> = dependencies
+            .iter()
+            .map(|(name, details)| (name, details))
+            .chain(nodes.iter().map(|node| (&node.name, &node.package)))
+            .filter_map(|(name, details)| match &details.mutual_exclusive {
+                ProjectSource::Registry(Some(url)) => Some((name.clone(), url.clone())),
+                _ => None,
             })
             .collect();
+        let mut visited = HashSet::new();
 
-        let mut selection = Vec::new();
+        while let Some((name, url)) = registry_queue.pop() {
+            // patched packages only come from their patched source
+            if self.patches.contains(&name) || !visited.insert((name.clone(), url.clone())) {
+                continue;
+            }
 
-        for (_, requirements, location) in merged {
-            //TODO: replace this in the future by first merging all the requirements
-            // (determine upper and lower bound)
+            let index_file = self
+                .registry(&url, git_clone_and_checkout_cap)?
+                .package(&name)?;
 
-            let mut filtered_results: Vec<&DependencyTreeNode> = location
-                .into_iter()
-                .filter(|location| {
-                    let filter = |version: &Versioning| {
-                        for requirement in &requirements {
-                            if !requirement.matches(version) {
-                                return false;
-                            }
-                        }
-                        true
-                    };
-
-                    filter(&location.version)
-                })
-                .collect();
+            for entry in index_file.versions {
+                // yanked versions stay available to packages that already locked them
+                if entry.yanked && self.locked_versions.get(&name) != Some(&entry.version) {
+                    continue;
+                }
 
-            if filtered_results.is_empty() {
-                error!("no viable package was found that fulfills all the requirements");
-            }
+                let mut requirements = Vec::new();
+                for (dependency, mut details) in entry.dependencies {
+                    // optional dependencies are only used once an enabled feature uses them
+                    if details.features.optional
+                        && !self
+                            .enabled_optional
+                            .contains(&(name.clone(), dependency.clone()))
+                    {
+                        continue;
+                    }
 
-            filtered_results.sort_by_key(|value| value.version.clone());
+                    details.inherit_registry(Some(&url));
+                    if let ProjectSource::Registry(Some(dependency_url)) = &details.mutual_exclusive
+                    {
+                        registry_queue.push((dependency.clone(), dependency_url.clone()));
+                    }
+                    requirements.push((dependency, details.version));
+                }
+                requirements.sort_by(|a, b| a.0.cmp(&b.0));
 
-            let package = filtered_results
-                .last()
-                .expect("There should be at least one viable package remaining!");
+                let known = candidates.entry(name.clone()).or_default();
+                if known.iter().any(|known| known.version == entry.version) {
+                    continue;
+                }
 
-            selection.push((*package).clone());
+                registry_candidates.insert((name.clone(), entry.version.to_string()), url.clone());
+                known.push(Candidate {
+                    version: entry.version,
+                    dependencies: requirements,
+                });
+            }
         }
 
-        let mut seen = HashSet::new();
-        let mut unique_nodes = Vec::new();
+        let mut root_requirements: Vec<(String, Requirement)> = dependencies
+            .iter()
+            .map(|(name, details)| (name.clone(), details.version.clone()))
+            .collect();
+        root_requirements.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let selected = resolver::resolve(root, &root_requirements, &candidates)
+            .map_err(|conflict| LingoError::DependencyResolution(conflict.to_string()))?;
+
+        let mut selection: Vec<DependencyTreeNode> = Vec::new();
+
+        // dependencies are placed before the packages that depend on them
+        for name in resolver::dependency_order(&selected) {
+            let version = &selected[&name].version;
 
-        for node in nodes.into_iter().rev() {
-            if seen.insert(node.name.clone()) {
-                unique_nodes.push(node);
+            let mut node = match nodes
+                .iter()
+                .find(|node| node.name == name && &node.version == version)
+            {
+                Some(node) => node.shallow_clone(),
+                None => {
+                    let url = registry_candidates[&(name.clone(), version.to_string())].clone();
+
+                    print!("{} {} ...", "Cloning".green().bold(), name);
+                    let (node, _) = self.non_recursive_fetching(
+                        &name,
+                        PackageDetails {
+                            version: Requirement::new(&format!("={version}")).unwrap_or_default(),
+                            mutual_exclusive: ProjectSource::Registry(Some(url)),
+                            git_tag: None,
+                            subdir: None,
+                            submodules: true,
+                            features: Default::default(),
+                            git_rev: None,
+                            archive_checksum: None,
+                            locked_version: None,
+                        },
+                        base_path,
+                        git_clone_and_checkout_cap,
+                        download_cap,
+                    )?;
+                    node
+                }
+            };
+
+            // links the selected versions of the dependencies so the lock knows about the edges
+            for (dependency, _) in &selected[&name].dependencies {
+                if let Some(child) = selection.iter().find(|child| &child.name == dependency) {
+                    node.dependencies.push(child.shallow_clone());
+                }
             }
+
+            selection.push(node);
         }
 
-        selection.sort_by(|a, b| {
-            let index_a = unique_nodes.iter().position(|node| node.name == a.name);
-            let index_b = unique_nodes.iter().position(|node| node.name == b.name);
+        Ok(selection)
+    }
 
-            index_a.cmp(&index_b)
+    /// target properties of the given packages and their dependencies
+    pub fn get_target_properties(
+        &self,
+        packages: &[String],
+    ) -> anyhow::Result<LibraryTargetProperties> {
+        self.lock.aggregate_target_properties(packages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::packaging::write_archive;
+    use crate::util::testing::{library_toml, write};
+    use crate::{DownloadError, GitCloneError};
+
+    #[test]
+    fn subdir_selects_package_inside_archive() {
+        let monorepo = tempdir().unwrap();
+        let sensors = monorepo.path().join("libs/sensors");
+        write(&sensors, "src/lib/Sensor.lf", "reactor Sensor {}");
+        write(&sensors, "Lingo.toml", &library_toml("sensors", "0.1.0"));
+        write(monorepo.path(), "README.md", "# monorepo");
+
+        let archive = monorepo.path().join("monorepo.tar.gz");
+        let files = [
+            "README.md",
+            "libs/sensors/Lingo.toml",
+            "libs/sensors/src/lib/Sensor.lf",
+        ]
+        .map(PathBuf::from);
+        write_archive(monorepo.path(), &files, "monorepo-main", &archive).unwrap();
+
+        let mut details = toml::from_str::<PackageDetails>(&format!(
+            "version = \"^0.1.0\"\ntarball = \"{}\"\nsubdir = \"libs/sensors\"\n",
+            Url::from_file_path(&archive).unwrap()
+        ))
+        .unwrap();
+
+        let clone: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let destination = tempdir().unwrap();
+        let library_path = destination.path().join("sensors");
+        details
+            .fetch("sensors", &library_path, true, &clone, &download)
+            .unwrap();
+        assert!(library_path.join("Lingo.toml").exists());
+        assert!(!library_path.join("README.md").exists());
+
+        let source = PackageLockSource::from(&details);
+        let serialized = toml::to_string(&HashMap::from([("source", &source)])).unwrap();
+        assert!(serialized.contains("?subdir=libs/sensors"));
+        assert_eq!(
+            PackageLockSource::from_str(&format!("tar+{}?subdir=libs/sensors", source.uri))
+                .unwrap_or_else(|_| panic!("cannot parse source")),
+            source
+        );
+
+        details.subdir = Some(PathBuf::from("../sensors"));
+        assert!(details
+            .fetch(
+                "sensors",
+                &destination.path().join("escape"),
+                true,
+                &clone,
+                &download
+            )
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn subdir_cannot_leave_the_checkout_through_symlinks() {
+        let outside = tempdir().unwrap();
+        write(
+            outside.path(),
+            "Lingo.toml",
+            &library_toml("sensors", "0.1.0"),
+        );
+
+        let target = outside.path().to_path_buf();
+        let clone: GitCloneAndCheckoutCap = Box::new(move |_, path, _, _| {
+            fs::create_dir_all(path).unwrap();
+            std::os::unix::fs::symlink(&target, path.join("sensors")).unwrap();
+            Ok(None)
         });
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let mut details = toml::from_str::<PackageDetails>(
+            "version = \"^0.1.0\"\ngit = \"https://example.org/monorepo.git\"\nsubdir = \"sensors\"\n",
+        )
+        .unwrap();
+
+        let destination = tempdir().unwrap();
+        let error = details
+            .fetch(
+                "sensors",
+                &destination.path().join("sensors"),
+                false,
+                &clone,
+                &download,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::InvalidSubdirectory(..))
+        ));
+        assert!(!destination.path().join("sensors/Lingo.toml").exists());
+    }
 
-        Ok(selection)
+    #[test]
+    fn cached_archives_must_match_the_lock() {
+        let package = tempdir().unwrap();
+        write(package.path(), "src/lib/Sensor.lf", "reactor Sensor {}");
+        write(
+            package.path(),
+            "Lingo.toml",
+            &library_toml("sensors", "0.1.0"),
+        );
+
+        let upstream = tempdir().unwrap();
+        let archive = upstream.path().join("sensors.tar.gz");
+        let files = ["Lingo.toml", "src/lib/Sensor.lf"].map(PathBuf::from);
+        write_archive(package.path(), &files, "sensors-0.1.0", &archive).unwrap();
+        let checksum = hash::file_checksum(&archive, Algorithm::Sha256).unwrap();
+
+        let details = toml::from_str::<PackageDetails>(
+            "version = \"^0.1.0\"\ntarball = \"https://example.org/sensors.tar.gz\"\n",
+        )
+        .unwrap();
+
+        // the archive was replaced upstream after it was cached
+        let cache_dir = tempdir().unwrap();
+        let cache = PackageCache::at(cache_dir.path().to_path_buf());
+        let stale = tempdir().unwrap();
+        write(
+            stale.path(),
+            "Lingo.toml",
+            &library_toml("sensors", "0.1.0"),
+        );
+        write(stale.path(), "stale.txt", "stale");
+        let entry = CacheEntry {
+            name: "sensors".to_string(),
+            version: Versioning::new("0.1.0").unwrap(),
+            checksum: package_checksum(stale.path()).unwrap(),
+            source: cache_source("sensors", &details),
+            rev: None,
+            archive_checksum: Some("sha256:00".to_string()),
+        };
+        cache.insert(&entry, stale.path()).unwrap();
+
+        let mut manager = DependencyManager {
+            cache: Some(cache),
+            locked_archives: HashMap::from([(
+                "sensors".to_string(),
+                (PackageLockSource::from(&details), checksum.clone()),
+            )]),
+            ..Default::default()
+        };
+
+        let clone: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+        let served = archive.clone();
+        let download: DownloadCap = Box::new(move |_, path| {
+            fs::copy(&served, path)
+                .map(|_| ())
+                .map_err(|e| DownloadError(e.to_string()))
+        });
+
+        let base_path = tempdir().unwrap();
+        let (node, _) = manager
+            .non_recursive_fetching(
+                "sensors",
+                details.clone(),
+                base_path.path(),
+                &clone,
+                &download,
+            )
+            .unwrap();
+        assert_eq!(node.package.archive_checksum, Some(checksum));
+        assert!(!node.location.join("stale.txt").exists());
+
+        // downloads are verified against the lock as well
+        manager.locked_archives.insert(
+            "sensors".to_string(),
+            (PackageLockSource::from(&details), "sha256:11".to_string()),
+        );
+        let error = manager
+            .non_recursive_fetching("sensors", details, base_path.path(), &clone, &download)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::ArchiveChecksumMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn diamond_dependencies_are_fetched_once() {
+        // a and b both depend on c
+        let clones = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = clones.clone();
+        let clone: GitCloneAndCheckoutCap = Box::new(move |url, path, _, _| {
+            counter.set(counter.get() + 1);
+            let url = <&str>::from(url);
+            let name = url.trim_end_matches(".git").rsplit('/').next().unwrap();
+            let dependencies = match name {
+                "c" => "",
+                _ => "c = { version = \">=0.1.0\", git = \"https://example.org/c.git\" }\n",
+            };
+            fs::create_dir_all(path.join("src/lib")).unwrap();
+            write(
+                path,
+                "Lingo.toml",
+                &(library_toml(name, "0.1.0") + dependencies),
+            );
+            Ok(Some("f372e81440da054de7444ee3ab16e6c1b3d4793c".to_string()))
+        });
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let dependencies = ["a", "b"]
+            .map(|name| {
+                let details = toml::from_str::<PackageDetails>(&format!(
+                    "version = \">=0.1.0\"\ngit = \"https://example.org/{name}.git\"\n"
+                ))
+                .unwrap();
+                (name.to_string(), details)
+            })
+            .to_vec();
+
+        let target = tempdir().unwrap();
+        let mut manager = DependencyManager::default();
+        let nodes = manager
+            .pull(dependencies, target.path(), &clone, &download)
+            .unwrap();
+
+        assert_eq!(clones.get(), 3);
+        assert!(nodes
+            .iter()
+            .all(|node| node.dependencies.len() == 1 && node.dependencies[0].name == "c"));
     }
 
-    pub fn get_target_properties(&self) -> anyhow::Result<LibraryTargetProperties> {
-        self.lock.aggregate_target_properties()
+    #[test]
+    fn optional_dependencies_are_only_fetched_when_enabled() {
+        // bridge is optional and depends on the optional extra that nothing enables
+        let cloned = std::rc::Rc::new(std::cell::RefCell::new(Vec::<String>::new()));
+        let recorder = cloned.clone();
+        let clone: GitCloneAndCheckoutCap = Box::new(move |url, path, _, _| {
+            let url = <&str>::from(url);
+            let name = url.trim_end_matches(".git").rsplit('/').next().unwrap();
+            recorder.borrow_mut().push(name.to_string());
+            let dependencies = match name {
+                "bridge" => "extra = { version = \">=0.1.0\", git = \"https://example.org/extra.git\", optional = true }\n",
+                _ => "",
+            };
+            fs::create_dir_all(path.join("src/lib")).unwrap();
+            write(
+                path,
+                "Lingo.toml",
+                &(library_toml(name, "0.1.0") + dependencies),
+            );
+            Ok(Some("f372e81440da054de7444ee3ab16e6c1b3d4793c".to_string()))
+        });
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let root = tempdir().unwrap();
+        let mut config = toml::from_str::<ConfigFile>(
+            "[package]\nname = \"plant\"\nversion = \"0.1.0\"\n\n[features]\nviz = [\"bridge\"]\n\n[dependencies]\nbridge = { version = \">=0.1.0\", git = \"https://example.org/bridge.git\", optional = true }\ntracing = { version = \">=0.1.0\", git = \"https://example.org/tracing.git\" }\n",
+        )
+        .unwrap()
+        .to_config(root.path());
+        let target_path = root.path().join("build");
+
+        let resolve = |config: &Config| {
+            cloned.borrow_mut().clear();
+            let mut manager = DependencyManager {
+                root: root.path().to_path_buf(),
+                ..Default::default()
+            };
+            manager
+                .resolve(
+                    config,
+                    config.all_dependencies().unwrap(),
+                    &target_path,
+                    &clone,
+                    &download,
+                )
+                .unwrap();
+            let mut locked: Vec<String> = manager.lock.dependencies.keys().cloned().collect();
+            locked.sort();
+            locked
+        };
+
+        assert_eq!(resolve(&config), ["tracing"]);
+        assert_eq!(*cloned.borrow(), ["tracing"]);
+
+        config.requested_features = vec!["viz".to_string()];
+        assert_eq!(resolve(&config), ["bridge", "tracing"]);
+        assert!(!cloned.borrow().contains(&"extra".to_string()));
     }
 }
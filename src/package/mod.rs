@@ -1,6 +1,18 @@
+pub mod cache;
+pub mod edit;
+pub mod features;
+pub mod graph;
 pub mod lock;
 pub mod management;
+pub mod outdated;
+pub mod packaging;
+pub mod patch;
+pub mod publish;
+pub mod registry;
+pub mod resolver;
 pub mod tree;
+pub mod vendor;
+pub mod workspace;
 
 pub mod target_properties;
 
@@ -9,6 +21,7 @@ use serde::{Deserializer, Serializer};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tempfile::tempdir;
+use url::Url;
 use versions::Versioning;
 
 use std::fs::{remove_dir_all, remove_file, write};
@@ -23,7 +36,10 @@ use crate::args::{
     BuildSystem::{CMake, LFC},
     InitArgs, Platform, TargetLanguage,
 };
+use crate::package::patch::Patches;
+use crate::package::registry::{RegistryFile, REGISTRY_ENV_VARIABLE};
 use crate::package::tree::GitLock;
+use crate::package::workspace::{same_source, WorkspaceMember};
 use crate::package::{
     target_properties::{
         AppTargetProperties, AppTargetPropertiesFile, LibraryTargetProperties,
@@ -35,7 +51,7 @@ use crate::util::{
     analyzer, copy_recursively,
     errors::{BuildResult, LingoError},
 };
-use crate::{FsReadCapability, GitCloneAndCheckoutCap, GitUrl, WhichCapability};
+use crate::{FsReadCapability, GitCloneAndCheckoutCap, GitCloneOptions, GitUrl, WhichCapability};
 
 /// place where are the build artifacts will be dropped
 pub const OUTPUT_DIRECTORY: &str = "build";
@@ -47,7 +63,7 @@ pub const LIBRARY_DIRECTORY: &str = "libraries";
 const DEFAULT_EXECUTABLE_FOLDER: &str = "src";
 
 /// default folder for lf library files
-const DEFAULT_LIBRARY_FOLDER: &str = "src/lib";
+pub(crate) const DEFAULT_LIBRARY_FOLDER: &str = "src/lib";
 
 fn is_valid_location_for_project(path: &std::path::Path) -> bool {
     !path.join(DEFAULT_EXECUTABLE_FOLDER).exists()
@@ -77,11 +93,33 @@ pub struct ConfigFile {
 
     /// Dependencies for required to build this Lingua-Franca Project
     pub dependencies: HashMap<String, PackageDetails>,
+
+    /// dependencies that are only available to examples and `lingo test`
+    #[serde(
+        rename = "dev-dependencies",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub dev_dependencies: HashMap<String, PackageDetails>,
+
+    /// features of the package and what they enable
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub features: HashMap<String, Vec<String>>,
+
+    /// registry that is used for dependencies without an explicit source
+    pub registry: Option<RegistryFile>,
+
+    /// sources that replace the ones of dependencies anywhere in the dependency graph
+    #[serde(default, skip_serializing_if = "Patches::is_empty")]
+    pub patch: Patches,
 }
 
 /// This struct is used after filling in all the defaults
 #[derive(Clone, Debug)]
 pub struct Config {
+    /// Absolute path to the directory with the Lingo.toml, all other paths start there.
+    pub root: PathBuf,
+
     /// top level package description
     pub package: PackageDescription,
 
@@ -93,6 +131,27 @@ pub struct Config {
 
     /// Dependencies for required to build this Lingua-Franca Project
     pub dependencies: HashMap<String, PackageDetails>,
+
+    /// dependencies that are only available to examples and `lingo test`
+    pub dev_dependencies: HashMap<String, PackageDetails>,
+
+    /// dependencies of all apps, they stay locked if only some apps are selected
+    pub app_dependencies: Vec<(String, PackageDetails)>,
+
+    /// features of the package and what they enable
+    pub features: HashMap<String, Vec<String>>,
+
+    /// features the dependencies are resolved with, the default feature if there is one
+    pub requested_features: Vec<String>,
+
+    /// registry that is used for dependencies without an explicit source
+    pub registry: Option<Url>,
+
+    /// sources that replace the ones of dependencies anywhere in the dependency graph
+    pub patches: Patches,
+
+    /// packages of the workspace, empty if this config describes a single package
+    pub members: Vec<WorkspaceMember>,
 }
 
 /// The Format inside the Lingo.toml under [lib]
@@ -150,6 +209,14 @@ pub struct AppFile {
     /// platform of this project
     pub platform: Option<Platform>,
 
+    /// dependencies only this app is built with
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub dependencies: HashMap<String, PackageDetails>,
+
+    /// examples are only built by `lingo test` or if they are selected explicitly
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub example: bool,
+
     /// target properties of that lingua-franca app
     pub properties: AppTargetPropertiesFile,
 }
@@ -170,6 +237,10 @@ pub struct App {
     pub target: TargetLanguage,
     /// platform for which this program should be compiled
     pub platform: Platform,
+    /// dependencies only this app is built with
+    pub dependencies: HashMap<String, PackageDetails>,
+    /// if the app is an example, which can use the dev-dependencies
+    pub example: bool,
     /// target properties of that lingua-franca app
     pub properties: AppTargetProperties,
 }
@@ -212,6 +283,8 @@ impl AppFile {
             main_reactor_name: main_reactor_name.to_string(),
             target: self.target,
             platform: self.platform.unwrap_or(Platform::Native),
+            dependencies: self.dependencies,
+            example: self.example,
             properties: self.properties.from(path),
         }
     }
@@ -284,6 +357,15 @@ impl App {
     }
 }
 
+/// the default feature is requested unless it is turned off, if the package has one
+pub(crate) fn default_features(table: &HashMap<String, Vec<String>>) -> Vec<String> {
+    table
+        .keys()
+        .filter(|&feature| feature == features::DEFAULT_FEATURE)
+        .cloned()
+        .collect()
+}
+
 fn serialize_version<S>(version: &Versioning, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -327,6 +409,12 @@ pub struct PackageDescription {
     pub website: Option<String>,
     pub license: Option<String>,
     pub description: Option<String>,
+    /// glob patterns of files that don't belong to the package content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// glob patterns of files that are packaged in addition to the library
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
 }
 
 impl ConfigFile {
@@ -351,6 +439,8 @@ impl ConfigFile {
                 main: Some(spec.path),
                 target: spec.target,
                 platform: Some(init_args.platform),
+                dependencies: HashMap::default(),
+                example: false,
                 properties: Default::default(),
             })
             .collect::<Vec<_>>();
@@ -369,10 +459,16 @@ impl ConfigFile {
                 website: None,
                 license: None,
                 description: None,
+                exclude: vec![],
+                include: vec![],
             },
             dependencies: HashMap::default(),
+            dev_dependencies: HashMap::default(),
+            features: HashMap::default(),
             apps: Some(app_specs),
             library: Option::default(),
+            registry: None,
+            patch: Patches::default(),
         };
         Ok(result)
     }
@@ -429,7 +525,12 @@ impl ConfigFile {
             None
         };
 
-        clone(GitUrl::from(url), tmp_path, git_rev)?;
+        clone(
+            GitUrl::from(url),
+            tmp_path,
+            git_rev,
+            GitCloneOptions::default(),
+        )?;
 
         // Copy the cloned template repo into the project directory
         copy_recursively(tmp_path, Path::new("."))?;
@@ -503,17 +604,214 @@ impl ConfigFile {
     pub fn to_config(self, path: &Path) -> Config {
         let package_name = &self.package.name;
 
+        // the registry from the Lingo.toml takes precedence over the environment
+        let registry = self.registry.map(|registry| registry.index).or_else(|| {
+            let value = env::var(REGISTRY_ENV_VARIABLE).ok()?;
+            Url::parse(&value)
+                .map_err(|e| log::warn!("ignoring invalid {REGISTRY_ENV_VARIABLE} {value}: {e}"))
+                .ok()
+        });
+
+        let mut dependencies = self.dependencies;
+        let mut dev_dependencies = self.dev_dependencies;
+        let mut apps = self
+            .apps
+            .unwrap_or_default()
+            .into_iter()
+            .map(|app_file| app_file.convert(package_name, path))
+            .collect::<Vec<_>>();
+        for details in dependencies
+            .values_mut()
+            .chain(dev_dependencies.values_mut())
+            .chain(
+                apps.iter_mut()
+                    .flat_map(|app| app.dependencies.values_mut()),
+            )
+        {
+            details.inherit_registry(registry.as_ref());
+        }
+
         Config {
+            root: path.to_path_buf(),
             //properties: self.properties,
-            apps: self
-                .apps
-                .unwrap_or_default()
-                .into_iter()
-                .map(|app_file| app_file.convert(package_name, path))
+            app_dependencies: apps
+                .iter()
+                .flat_map(|app| app.dependencies.clone())
                 .collect(),
+            apps,
             package: self.package.clone(),
             library: self.library.map(|lib| lib.convert(package_name, path)),
-            dependencies: self.dependencies,
+            dependencies,
+            dev_dependencies,
+            requested_features: default_features(&self.features),
+            features: self.features,
+            registry,
+            patches: self.patch,
+            members: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Dependencies of the package or of all workspace members including the ones of the
+    /// apps and the dev-dependencies, they are all locked together. Declarations that share a
+    /// dependency each add their own requirement but have to agree on its source.
+    pub fn all_dependencies(&self) -> anyhow::Result<Vec<(String, PackageDetails)>> {
+        let mut dependencies = Vec::from_iter(self.dependencies.clone());
+        dependencies.extend(self.dev_dependencies.clone());
+        for member in &self.members {
+            dependencies.extend(member.dependencies.clone());
+            dependencies.extend(member.dev_dependencies.clone());
+        }
+        dependencies.extend(self.app_dependencies.clone());
+        dependencies.sort_by(|a, b| a.0.cmp(&b.0));
+
+        if let Some([(name, _), _]) = dependencies
+            .windows(2)
+            .find(|pair| pair[0].0 == pair[1].0 && !same_source(&pair[0].1, &pair[1].1))
+        {
+            return Err(LingoError::ConflictingDependency(name.clone()).into());
+        }
+        dependencies.dedup_by(|a, b| {
+            a.0 == b.0 && a.1.version == b.1.version && a.1.features == b.1.features
+        });
+        Ok(dependencies)
+    }
+
+    /// Names of the dependencies the app is built with, the ones of the package it belongs
+    /// to and its own. Dev-dependencies are added for examples or if `dev` is set.
+    pub fn app_dependency_names(&self, app: &App, dev: bool) -> Vec<String> {
+        let (dependencies, dev_dependencies) = match self
+            .members
+            .iter()
+            .find(|member| member.path == app.root_path)
+        {
+            Some(member) => (&member.dependencies, &member.dev_dependencies),
+            None => (&self.dependencies, &self.dev_dependencies),
+        };
+
+        let mut names = Vec::from_iter(dependencies.keys().cloned());
+        names.extend(app.dependencies.keys().cloned());
+        if dev || app.example {
+            names.extend(dev_dependencies.keys().cloned());
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Names of the dependencies that are needed to build the selected apps, packages without
+    /// apps need their own dependencies.
+    pub fn build_dependency_names(&self, dev: bool) -> Vec<String> {
+        let mut names = vec![];
+        for app in &self.apps {
+            names.extend(self.app_dependency_names(app, dev));
+        }
+        if self.apps.is_empty() {
+            names.extend(self.dependencies.keys().cloned());
+            for member in &self.members {
+                names.extend(member.dependencies.keys().cloned());
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Selects the features the dependencies are resolved with. Features are entries of the
+    /// `[features]` table, optional dependencies or features of dependencies.
+    pub fn select_features(&mut self, features: &[String], default: bool) -> BuildResult {
+        let dependencies = self.all_dependencies()?;
+        let unknown_names = features
+            .iter()
+            .filter(|&feature| {
+                let name = feature
+                    .split_once('/')
+                    .map_or(feature.as_str(), |(name, _)| name);
+                !self.features.contains_key(feature)
+                    && !dependencies.iter().any(|(dependency, details)| {
+                        dependency == name && (details.features.optional || name != feature)
+                    })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unknown_names.is_empty() {
+            return Err(Box::new(LingoError::UnknownFeatures(unknown_names)));
         }
+
+        let mut requested = features.to_vec();
+        if default {
+            requested.extend(default_features(&self.features));
+        }
+        requested.sort();
+        requested.dedup();
+        self.requested_features = requested;
+        Ok(())
+    }
+
+    /// only keeps the apps of the given packages, which are workspace members or the package
+    pub fn select_packages(&mut self, names: &[String]) -> BuildResult {
+        let unknown_names = names
+            .iter()
+            .filter(|&name| {
+                name != &self.package.name
+                    && !self.members.iter().any(|member| &member.name == name)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unknown_names.is_empty() {
+            return Err(Box::new(LingoError::UnknownWorkspaceMembers(unknown_names)));
+        }
+
+        if self.members.is_empty() {
+            return Ok(());
+        }
+
+        let selected = self
+            .members
+            .iter()
+            .filter(|member| names.contains(&member.name))
+            .map(|member| &member.path)
+            .collect::<Vec<_>>();
+        self.apps.retain(|app| selected.contains(&&app.root_path));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dependencies: &str, app_dependencies: &str) -> Config {
+        toml::from_str::<ConfigFile>(&format!(
+            "[package]\nname = \"plant\"\nversion = \"0.1.0\"\n\n[[app]]\nname = \"plant\"\ntarget = \"Cpp\"\ndependencies = {{ {app_dependencies} }}\n\n[app.properties]\n\n[dependencies]\n{dependencies}\n"
+        ))
+        .unwrap()
+        .to_config(Path::new("/plant"))
+    }
+
+    #[test]
+    fn declarations_of_a_dependency_share_its_source() {
+        let same = config(
+            "mqtt = { version = \">=0.1\", git = \"https://example.org/mqtt.git\" }",
+            "mqtt = { version = \">=0.1\", git = \"https://example.org/mqtt.git\" }",
+        );
+        assert_eq!(same.all_dependencies().unwrap().len(), 1);
+
+        let stricter = config(
+            "mqtt = { version = \">=0.1\", git = \"https://example.org/mqtt.git\" }",
+            "mqtt = { version = \">=0.2\", git = \"https://example.org/mqtt.git\" }",
+        );
+        assert_eq!(stricter.all_dependencies().unwrap().len(), 2);
+
+        let forked = config(
+            "mqtt = { version = \">=0.1\", git = \"https://example.org/mqtt.git\" }",
+            "mqtt = { version = \">=0.1\", git = \"https://example.org/fork/mqtt.git\" }",
+        );
+        let error = forked.all_dependencies().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::ConflictingDependency(name)) if name == "mqtt"
+        ));
     }
 }
@@ -0,0 +1,223 @@
+use versions::{Requirement, SemVer, Versioning};
+
+use std::fs;
+use std::path::Path;
+
+use crate::package::lock::DependencyLock;
+use crate::package::registry::RegistryIndex;
+use crate::package::tree::{PackageDetails, ProjectSource};
+use crate::package::ConfigFile;
+use crate::{GitCloneAndCheckoutCap, GitListTagsCap, GitUrl};
+
+/// Versions of a dependency compared to what is available from its source
+#[derive(Debug, PartialEq)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub requirement: Requirement,
+    /// version in the Lingo.lock
+    pub locked: Option<Versioning>,
+    /// newest available version that satisfies the requirement
+    pub compatible: Option<Versioning>,
+    /// newest available version
+    pub latest: Option<Versioning>,
+}
+
+/// version a git tag stands for, only semver tags with an optional `v` prefix count
+fn tag_version(tag: &str) -> Option<Versioning> {
+    SemVer::new(tag.strip_prefix('v').unwrap_or(tag)).map(Versioning::Ideal)
+}
+
+/// all versions the source of the package offers, relative paths start at `root`
+fn available_versions(
+    name: &str,
+    details: &PackageDetails,
+    root: &Path,
+    git_list_tags_cap: &GitListTagsCap,
+    git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+) -> anyhow::Result<Vec<Versioning>> {
+    match &details.mutual_exclusive {
+        ProjectSource::Git(url) => Ok(git_list_tags_cap(GitUrl::from(url.as_str()))?
+            .iter()
+            .filter_map(|tag| tag_version(tag))
+            .collect()),
+        ProjectSource::Registry(Some(url)) => {
+            let index = RegistryIndex::open(url, git_clone_and_checkout_cap)?;
+            Ok(index
+                .package(name)?
+                .versions
+                .into_iter()
+                .filter(|entry| !entry.yanked)
+                .map(|entry| entry.version)
+                .collect())
+        }
+        // a local package only offers the version it currently has
+        ProjectSource::Path(path) => {
+            let lingo_toml_text = fs::read_to_string(root.join(path).join("Lingo.toml"))?;
+            let config_file = toml::from_str::<ConfigFile>(&lingo_toml_text)?;
+            Ok(vec![config_file.package.version])
+        }
+        // archives and packages without registry don't tell which other versions exist
+        ProjectSource::TarBall(_) | ProjectSource::Registry(None) => Ok(vec![]),
+    }
+}
+
+/// Compares the locked version of every direct dependency with the versions its source offers.
+/// Sources that cannot be queried are reported with a warning and without versions.
+pub fn outdated(
+    dependencies: &[(String, PackageDetails)],
+    root: &Path,
+    lock: &DependencyLock,
+    git_list_tags_cap: &GitListTagsCap,
+    git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+) -> Vec<OutdatedPackage> {
+    let mut dependencies = dependencies.to_vec();
+    dependencies.sort_by(|a, b| a.0.cmp(&b.0));
+
+    dependencies
+        .into_iter()
+        .map(|(name, details)| {
+            let versions = available_versions(
+                &name,
+                &details,
+                root,
+                git_list_tags_cap,
+                git_clone_and_checkout_cap,
+            )
+            .unwrap_or_else(|e| {
+                log::warn!("cannot look up the versions of {name}: {e}");
+                vec![]
+            });
+
+            OutdatedPackage {
+                locked: lock
+                    .dependencies
+                    .get(&name)
+                    .map(|lock| lock.version.clone()),
+                compatible: versions
+                    .iter()
+                    .filter(|version| details.version.matches(version))
+                    .max()
+                    .cloned(),
+                latest: versions.into_iter().max(),
+                requirement: details.version,
+                name,
+            }
+        })
+        .collect()
+}
+
+/// reads the Lingo.lock if there is one, packages that are not locked show no locked version
+pub fn read_lock(lock_file: &Path) -> anyhow::Result<DependencyLock> {
+    if !lock_file.exists() {
+        return Ok(DependencyLock::default());
+    }
+
+    DependencyLock::read(lock_file)
+}
+
+/// formats the packages as a table with one row per package
+pub fn format_table(packages: &[OutdatedPackage]) -> String {
+    let show = |version: &Option<Versioning>| {
+        version
+            .as_ref()
+            .map(|version| version.to_string())
+            .unwrap_or("-".to_string())
+    };
+
+    let mut rows = vec![[
+        "Name".to_string(),
+        "Requirement".to_string(),
+        "Locked".to_string(),
+        "Compatible".to_string(),
+        "Latest".to_string(),
+    ]];
+    for package in packages {
+        rows.push([
+            package.name.clone(),
+            package.requirement.to_string(),
+            show(&package.locked),
+            show(&package.compatible),
+            show(&package.latest),
+        ]);
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GitCloneError;
+
+    #[test]
+    fn reports_versions_from_git_tags() {
+        let lock = DependencyLock::parse(
+            r#"
+[mqtt]
+name = "mqtt"
+version = "0.1.0"
+source = "git+https://example.org/mqtt.git#f372e81440da054de7444ee3ab16e6c1b3d4793c"
+checksum = "sha256:01"
+"#,
+        )
+        .unwrap();
+
+        let details = toml::from_str::<PackageDetails>(
+            r#"version = "^0.1.0"
+git = "https://example.org/mqtt.git"
+branch = "main"
+"#,
+        )
+        .unwrap();
+
+        let list_tags: GitListTagsCap = Box::new(|url| {
+            assert_eq!(<&str>::from(url), "https://example.org/mqtt.git");
+            Ok(["v0.1.0", "v0.1.3", "0.2.0", "nightly"]
+                .map(String::from)
+                .to_vec())
+        });
+        let clone: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+
+        let packages = outdated(
+            &[("mqtt".to_string(), details)],
+            Path::new("."),
+            &lock,
+            &list_tags,
+            &clone,
+        );
+
+        assert_eq!(
+            packages,
+            vec![OutdatedPackage {
+                name: "mqtt".to_string(),
+                requirement: Requirement::new("^0.1.0").unwrap(),
+                locked: Versioning::new("0.1.0"),
+                compatible: Versioning::new("0.1.3"),
+                latest: Versioning::new("0.2.0"),
+            }]
+        );
+        assert_eq!(
+            format_table(&packages),
+            "Name  Requirement  Locked  Compatible  Latest\nmqtt  ^0.1.0       0.1.0   0.1.3       0.2.0\n"
+        );
+    }
+}
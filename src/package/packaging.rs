@@ -0,0 +1,375 @@
+use flate2::{Compression, GzBuilder};
+use globset::{Glob, GlobSetBuilder};
+use tempfile::tempdir;
+use url::Url;
+use versions::Requirement;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::package::management::{package_checksum, DependencyManager};
+use crate::package::tree::{PackageDetails, ProjectSource};
+use crate::package::{ConfigFile, DEFAULT_LIBRARY_FOLDER, OUTPUT_DIRECTORY};
+use crate::util::errors::LingoError;
+use crate::util::hash::{self, Algorithm, Ignore};
+use crate::{DownloadCap, GitCloneAndCheckoutCap};
+
+/// name of the folder inside the output directory the archives are written to
+pub const PACKAGE_DIRECTORY: &str = "package";
+
+/// Result of packaging a library
+pub struct PackagedLibrary {
+    pub archive: PathBuf,
+    /// number of files inside the archive
+    pub files: usize,
+    /// checksum of the archive file
+    pub archive_checksum: String,
+    /// checksum of the package content like it is recorded in the Lingo.lock
+    pub checksum: String,
+}
+
+fn read_config(root: &Path) -> anyhow::Result<ConfigFile> {
+    let lingo_toml_text = fs::read_to_string(root.join("Lingo.toml"))?;
+    Ok(toml::from_str::<ConfigFile>(&lingo_toml_text)?)
+}
+
+fn collect_files(
+    root: &Path,
+    relative: &Path,
+    ignore: &Ignore,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+
+        if ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            collect_files(root, &path, ignore, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Files of the package in `root` that belong into its archive: the Lingo.toml, the library
+/// location, the cmake include, the sources and artifacts of the library and everything that
+/// matches `include`. Excluded files are left out. The paths are relative and sorted.
+pub fn package_files(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let config_file = read_config(root)?;
+    let library = config_file.library.ok_or(LingoError::NoLibraryInLingoToml(
+        root.join("Lingo.toml").display().to_string(),
+    ))?;
+
+    let mut required = vec![
+        PathBuf::from("Lingo.toml"),
+        library
+            .location
+            .clone()
+            .unwrap_or(DEFAULT_LIBRARY_FOLDER.into()),
+    ];
+    required.extend(library.properties.files().into_iter().cloned());
+
+    // paths are compared without a leading ./
+    let required: Vec<PathBuf> = required
+        .iter()
+        .map(|path| path.components().collect())
+        .collect();
+
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|path| !root.join(path).exists())
+        .map(|path| path.display().to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(LingoError::MissingPackageFiles(missing).into());
+    }
+
+    let mut include = GlobSetBuilder::new();
+    for pattern in &config_file.package.include {
+        include.add(Glob::new(pattern)?);
+    }
+    let include = include.build()?;
+
+    let mut files = Vec::new();
+    let ignore = Ignore::new(&config_file.package.exclude)?;
+    collect_files(root, Path::new(""), &ignore, &mut files)?;
+
+    files.retain(|file| {
+        required.iter().any(|path| file.starts_with(path)) || include.is_match(file)
+    });
+    files.sort();
+
+    Ok(files)
+}
+
+/// Writes the files into a .tar.gz below the directory `prefix`. The archive only depends on
+/// the file names and contents, timestamps, owners and permissions are normalized.
+pub fn write_archive(
+    root: &Path,
+    files: &[PathBuf],
+    prefix: &str,
+    destination: &Path,
+) -> io::Result<()> {
+    let encoder = GzBuilder::new().write(File::create(destination)?, Compression::best());
+    let mut builder = tar::Builder::new(encoder);
+
+    for file in files {
+        let metadata = fs::metadata(root.join(file))?;
+
+        let mut header = tar::Header::new_ustar();
+        header.set_size(metadata.len());
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mode(if is_executable(&metadata) {
+            0o755
+        } else {
+            0o644
+        });
+
+        // paths inside the archive always use forward slashes
+        let path = file
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        builder.append_data(
+            &mut header,
+            format!("{prefix}/{path}"),
+            File::open(root.join(file))?,
+        )?;
+    }
+
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Consumes the archive like a tarball dependency: the extracted package has to export its
+/// library with all files and its dependencies have to resolve without anything from the
+/// package directory. Returns the checksum of the extracted content.
+pub fn verify(
+    archive: &Path,
+    config_file: &ConfigFile,
+    git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+    download_cap: &DownloadCap,
+) -> anyhow::Result<String> {
+    let name = &config_file.package.name;
+    let url = Url::from_file_path(fs::canonicalize(archive)?)
+        .map_err(|_| LingoError::UnsupportedArchiveFormat(archive.display().to_string()))?;
+
+    let mut details = PackageDetails {
+        version: Requirement::new(&format!("={}", config_file.package.version)).unwrap_or_default(),
+        mutual_exclusive: ProjectSource::TarBall(url),
+        git_tag: None,
+        subdir: None,
+        submodules: true,
+        features: Default::default(),
+        git_rev: None,
+        archive_checksum: None,
+        locked_version: None,
+    };
+
+    let extracted = tempdir()?;
+    let location = extracted.path().join(name);
+    details.fetch(
+        name,
+        &location,
+        true,
+        git_clone_and_checkout_cap,
+        download_cap,
+    )?;
+
+    let packaged = read_config(&location)?;
+    if !details.version.matches(&packaged.package.version) {
+        return Err(LingoError::LingoVersionMismatch(format!(
+            "requested version {} got version {}",
+            details.version, packaged.package.version
+        ))
+        .into());
+    }
+
+    // checks that the library and all of its files made it into the archive
+    package_files(&location)?;
+
+    // relative paths don't point to anything once the archive is extracted somewhere else
+    let path_dependencies: Vec<String> = packaged
+        .dependencies
+        .iter()
+        .filter(|(_, details)| matches!(details.mutual_exclusive, ProjectSource::Path(_)))
+        .map(|(name, _)| name.clone())
+        .collect();
+    if !path_dependencies.is_empty() {
+        return Err(LingoError::PathDependenciesInPackage(path_dependencies).into());
+    }
+
+    let checksum = package_checksum(&location)?;
+
+    // the dependencies are fetched from their sources the way a consumer gets them
+    DependencyManager::isolated(
+        &packaged.to_config(&location),
+        &location.join(OUTPUT_DIRECTORY),
+        git_clone_and_checkout_cap,
+        download_cap,
+    )?;
+
+    Ok(checksum)
+}
+
+/// Packs the library of the package in `root` into `<output>/<name>-<version>.tar.gz` and
+/// verifies the archive unless `verify_archive` is false.
+pub fn package(
+    root: &Path,
+    output: &Path,
+    verify_archive: bool,
+    git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+    download_cap: &DownloadCap,
+) -> anyhow::Result<PackagedLibrary> {
+    let config_file = read_config(root)?;
+    let files = package_files(root)?;
+
+    let prefix = format!(
+        "{}-{}",
+        config_file.package.name, config_file.package.version
+    );
+    fs::create_dir_all(output)?;
+    let archive = output.join(format!("{prefix}.tar.gz"));
+    write_archive(root, &files, &prefix, &archive)?;
+
+    let checksum = if verify_archive {
+        verify(
+            &archive,
+            &config_file,
+            git_clone_and_checkout_cap,
+            download_cap,
+        )?
+    } else {
+        let extracted = tempdir()?;
+        crate::util::archive::extract(
+            &archive,
+            crate::util::archive::ArchiveFormat::TarGz,
+            extracted.path(),
+        )?;
+        package_checksum(extracted.path())?
+    };
+
+    Ok(PackagedLibrary {
+        archive_checksum: hash::file_checksum(&archive, Algorithm::Sha256)?,
+        archive,
+        files: files.len(),
+        checksum,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::{library_toml, write};
+    use crate::DownloadError;
+    use crate::GitCloneError;
+
+    #[test]
+    fn archives_are_deterministic_and_consumable() {
+        let root = tempdir().unwrap();
+        write(
+            root.path(),
+            "Lingo.toml",
+            "[package]\nname = \"mqtt\"\nversion = \"0.1.0\"\ninclude = [\"README.md\"]\nexclude = [\"src/lib/*.log\"]\n\n[lib]\ntarget = \"Cpp\"\n\n[lib.properties]\ncmake-include = \"mqtt.cmake\"\n\n[dependencies]\n",
+        );
+        write(root.path(), "src/lib/Mqtt.lf", "reactor Mqtt {}");
+        write(root.path(), "src/lib/debug.log", "log");
+        write(root.path(), "mqtt.cmake", "");
+        write(root.path(), "README.md", "# mqtt");
+        write(root.path(), "notes.txt", "not packaged");
+        write(root.path(), "build/lfc_include/out.txt", "artifact");
+
+        assert_eq!(
+            package_files(root.path()).unwrap(),
+            ["Lingo.toml", "README.md", "mqtt.cmake", "src/lib/Mqtt.lf"].map(PathBuf::from)
+        );
+
+        let clone: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let first = tempdir().unwrap();
+        let second = tempdir().unwrap();
+        let packaged = package(root.path(), first.path(), true, &clone, &download).unwrap();
+        let repackaged = package(root.path(), second.path(), false, &clone, &download).unwrap();
+
+        assert_eq!(packaged.files, 4);
+        assert_eq!(packaged.archive_checksum, repackaged.archive_checksum);
+        assert_eq!(packaged.checksum, repackaged.checksum);
+        assert_eq!(
+            fs::read(&packaged.archive).unwrap(),
+            fs::read(&repackaged.archive).unwrap()
+        );
+
+        fs::remove_file(root.path().join("mqtt.cmake")).unwrap();
+        assert!(package_files(root.path()).is_err());
+    }
+
+    #[test]
+    fn verification_resolves_the_dependencies() {
+        let root = tempdir().unwrap();
+        write(
+            root.path(),
+            "Lingo.toml",
+            &(library_toml("mqtt", "0.1.0")
+                + "util = { version = \">=0.1.0\", git = \"https://example.org/util.git\" }\n"),
+        );
+        write(root.path(), "src/lib/Mqtt.lf", "reactor Mqtt {}");
+
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+        let offline: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+        let output = tempdir().unwrap();
+        assert!(package(root.path(), output.path(), true, &offline, &download).is_err());
+        assert!(package(root.path(), output.path(), false, &offline, &download).is_ok());
+
+        let clone: GitCloneAndCheckoutCap = Box::new(|_, path, _, _| {
+            write(path, "Lingo.toml", &library_toml("util", "0.1.0"));
+            write(path, "src/lib/Util.lf", "reactor Util {}");
+            Ok(Some("f372e81440da054de7444ee3ab16e6c1b3d4793c".to_string()))
+        });
+        let packaged = package(root.path(), output.path(), true, &clone, &download).unwrap();
+        let repackaged = package(root.path(), output.path(), false, &clone, &download).unwrap();
+        assert_eq!(packaged.checksum, repackaged.checksum);
+
+        // path dependencies only exist next to the package that was packed
+        write(
+            root.path(),
+            "Lingo.toml",
+            &(library_toml("mqtt", "0.1.0")
+                + "util = { version = \">=0.1.0\", path = \"../util\" }\n"),
+        );
+        let Err(error) = package(root.path(), output.path(), true, &clone, &download) else {
+            panic!("path dependencies were packed");
+        };
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::PathDependenciesInPackage(_))
+        ));
+    }
+}
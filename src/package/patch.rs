@@ -0,0 +1,144 @@
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::package::lock::{DependencyLock, PackageLockSource};
+use crate::package::tree::{checkout_submodules, is_true, GitLock, PackageDetails, ProjectSource};
+use crate::util::errors::LingoError;
+
+/// Source a package is taken from instead of the one its dependents ask for
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Patch {
+    #[serde(flatten)]
+    pub(crate) source: ProjectSource,
+    #[serde(flatten)]
+    pub(crate) git_tag: Option<GitLock>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) subdir: Option<PathBuf>,
+    #[serde(default = "checkout_submodules", skip_serializing_if = "is_true")]
+    pub(crate) submodules: bool,
+}
+
+/// The `[patch]` table of the root package. Every package with a patch is fetched from the
+/// patched source, no matter which package in the dependency graph requires it.
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+#[serde(transparent)]
+pub struct Patches {
+    patches: HashMap<String, Patch>,
+}
+
+impl Patches {
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.patches.contains_key(name)
+    }
+
+    /// names of the patched packages
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.patches.keys()
+    }
+
+    /// patches the sources of the given dependencies
+    pub fn apply_all(&self, dependencies: &mut [(String, PackageDetails)]) {
+        for (name, details) in dependencies {
+            self.apply(name, details);
+        }
+    }
+
+    /// Replaces the source of the package if it is patched, the version requirement stays
+    /// the same. Returns true if the package was patched.
+    pub fn apply(&self, name: &str, details: &mut PackageDetails) -> bool {
+        let Some(patch) = self.patches.get(name) else {
+            return false;
+        };
+
+        details.mutual_exclusive = patch.source.clone();
+        details.git_tag = patch.git_tag.clone();
+        details.subdir = patch.subdir.clone();
+        details.submodules = patch.submodules;
+        details.git_rev = None;
+        details.archive_checksum = None;
+        true
+    }
+
+    /// checks that the locked packages were taken from their patched sources
+    pub fn check_lock(&self, lock: &DependencyLock) -> anyhow::Result<()> {
+        for (name, locked) in &lock.dependencies {
+            if !self.contains(name) {
+                continue;
+            }
+
+            let mut details = PackageDetails::try_from(&locked.source)?;
+            self.apply(name, &mut details);
+
+            let patched = PackageLockSource::from(&details);
+            if patched.source_type != locked.source.source_type
+                || patched.uri != locked.source.uri
+                || patched.subdir != locked.source.subdir
+                || patched.submodules != locked.source.submodules
+            {
+                return Err(LingoError::LockFileOutdated(format!(
+                    "patched source of {name} changed"
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::lock::PackageLock;
+    use std::str::FromStr;
+    use versions::Versioning;
+
+    #[test]
+    fn patches_replace_source_and_keep_requirement() {
+        let patches = toml::from_str::<Patches>(
+            "mqtt = { path = \"../mqtt\" }\nsensors = { git = \"https://example.org/sensors.git\", branch = \"fix\" }\n",
+        )
+        .unwrap();
+
+        let mut details = toml::from_str::<PackageDetails>(
+            "version = \"^0.2\"\ngit = \"https://example.org/mqtt.git\"\ntag = \"v0.2.0\"\n",
+        )
+        .unwrap();
+        let requirement = details.version.clone();
+
+        assert!(patches.apply("mqtt", &mut details));
+        assert_eq!(details.version, requirement);
+        assert!(details.git_tag.is_none());
+        assert!(matches!(details.mutual_exclusive, ProjectSource::Path(_)));
+        assert!(!patches.apply("websocket", &mut details));
+
+        let mut lock = DependencyLock::default();
+        lock.dependencies.insert(
+            "sensors".to_string(),
+            PackageLock {
+                name: "sensors".to_string(),
+                version: Versioning::new("1.0.0").unwrap(),
+                source: PackageLockSource::from_str(
+                    "git+https://example.org/sensors.git#0123456789abcdef",
+                )
+                .unwrap_or_else(|_| panic!("cannot parse source")),
+                checksum: String::new(),
+                archive_checksum: None,
+                dependencies: vec![],
+                location: None,
+                cmake_include: None,
+                features: vec![],
+            },
+        );
+        assert!(patches.check_lock(&lock).is_ok());
+
+        lock.dependencies["sensors"].source.uri = "https://example.org/upstream.git".to_string();
+        assert!(patches.check_lock(&lock).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use url::Url;
+use versions::Versioning;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::package::lock::{PackageLockSource, PackageLockSourceType};
+use crate::package::packaging::{self, PACKAGE_DIRECTORY};
+use crate::package::registry::{IndexEntry, IndexFile, RegistryIndex};
+use crate::package::{ConfigFile, OUTPUT_DIRECTORY};
+use crate::util::errors::LingoError;
+use crate::{DownloadCap, GitCloneAndCheckoutCap};
+
+/// folder inside a local registry index the published archives are copied to
+pub const ARCHIVE_DIRECTORY: &str = "archives";
+
+/// Parses the registry given on the command line, existing directories are local indices and
+/// everything else has to be the url of a git repository.
+pub fn registry_url(registry: &str) -> anyhow::Result<Url> {
+    let path = Path::new(registry);
+    if path.is_dir() {
+        return Url::from_directory_path(fs::canonicalize(path)?)
+            .map_err(|_| LingoError::InvalidRegistry(registry.to_string()).into());
+    }
+
+    Url::parse(registry).map_err(|_| LingoError::InvalidRegistry(registry.to_string()).into())
+}
+
+/// registry given on the command line or else the one of the package
+pub fn select_registry(argument: Option<&str>, configured: Option<&Url>) -> anyhow::Result<Url> {
+    match (argument, configured) {
+        (Some(registry), _) => registry_url(registry),
+        (None, Some(registry)) => Ok(registry.clone()),
+        (None, None) => Err(LingoError::NoRegistryGiven.into()),
+    }
+}
+
+/// Files inside the git working tree of `root` that have uncommitted changes. Packages that
+/// aren't inside a git repository have none.
+fn uncommitted_changes(root: &Path) -> anyhow::Result<Vec<String>> {
+    let output = Command::new("git")
+        .arg("status")
+        .arg("--porcelain")
+        .arg("--untracked-files=normal")
+        .arg(".")
+        .current_dir(root)
+        .output()?;
+
+    if !output.status.success() {
+        return Ok(vec![]);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(String::from)
+        .collect())
+}
+
+/// published versions of the package, a package that was never published has none
+fn published(index: &RegistryIndex, name: &str) -> anyhow::Result<IndexFile> {
+    if index.metadata_path(name)?.exists() {
+        index.package(name)
+    } else {
+        Ok(IndexFile::default())
+    }
+}
+
+/// Packages the library in `root` and adds the new version to the registry index. The
+/// archive is copied into local indices, git indices need the url it is uploaded to.
+pub fn publish(
+    root: &Path,
+    registry: &Url,
+    archive_url: Option<&Url>,
+    allow_dirty: bool,
+    git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+    download_cap: &DownloadCap,
+) -> anyhow::Result<IndexEntry> {
+    if !allow_dirty {
+        let changes = uncommitted_changes(root)?;
+        if !changes.is_empty() {
+            return Err(LingoError::DirtyWorkingTree(changes).into());
+        }
+    }
+
+    let lingo_toml_text = fs::read_to_string(root.join("Lingo.toml"))?;
+    let config_file = toml::from_str::<ConfigFile>(&lingo_toml_text)?;
+    let name = &config_file.package.name;
+    let version = &config_file.package.version;
+
+    let index = RegistryIndex::open(registry, git_clone_and_checkout_cap)?;
+    let mut index_file = published(&index, name)?;
+    if index_file
+        .versions
+        .iter()
+        .any(|entry| &entry.version == version)
+    {
+        return Err(LingoError::VersionAlreadyPublished(name.clone(), version.to_string()).into());
+    }
+
+    let packaged = packaging::package(
+        root,
+        &root.join(OUTPUT_DIRECTORY).join(PACKAGE_DIRECTORY),
+        true,
+        git_clone_and_checkout_cap,
+        download_cap,
+    )?;
+
+    let archive_url = match archive_url {
+        Some(url) => url.clone(),
+        None if index.is_git() => {
+            return Err(LingoError::ArchiveLocationRequired(registry.to_string()).into())
+        }
+        None => {
+            let destination = index
+                .root()
+                .join(ARCHIVE_DIRECTORY)
+                .join(packaged.archive.file_name().unwrap());
+            fs::create_dir_all(destination.parent().unwrap())?;
+            fs::copy(&packaged.archive, &destination)?;
+            Url::from_file_path(fs::canonicalize(&destination)?)
+                .map_err(|_| LingoError::InvalidRegistry(registry.to_string()))?
+        }
+    };
+
+    // dependencies without source stay without, they are looked up in the same registry
+    let entry = IndexEntry {
+        version: version.clone(),
+        source: PackageLockSource {
+            source_type: PackageLockSourceType::TARBALL,
+            uri: archive_url.to_string(),
+            rev: None,
+            subdir: None,
+            submodules: true,
+            git_ref: None,
+        },
+        checksum: packaged.checksum,
+        archive_checksum: Some(packaged.archive_checksum),
+        dependencies: config_file.dependencies,
+        yanked: false,
+    };
+
+    index_file.versions.push(entry.clone());
+    index_file
+        .versions
+        .sort_by(|a, b| a.version.cmp(&b.version));
+    index.write_package(name, &index_file)?;
+    index.commit(&format!("Publish {name} {version}"))?;
+
+    Ok(entry)
+}
+
+/// Marks a published version of the package as yanked or, with `undo`, as available again
+pub fn yank(
+    name: &str,
+    version: &Versioning,
+    registry: &Url,
+    undo: bool,
+    git_clone_and_checkout_cap: &GitCloneAndCheckoutCap,
+) -> anyhow::Result<()> {
+    let index = RegistryIndex::open(registry, git_clone_and_checkout_cap)?;
+    let mut index_file = index.package(name)?;
+
+    let entry = index_file
+        .versions
+        .iter_mut()
+        .find(|entry| &entry.version == version)
+        .ok_or(LingoError::NoMatchingVersion(
+            name.to_string(),
+            format!("={version}"),
+        ))?;
+    entry.yanked = !undo;
+
+    index.write_package(name, &index_file)?;
+    index.commit(&format!(
+        "{} {name} {version}",
+        if undo { "Unyank" } else { "Yank" }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::{library_toml, write};
+    use crate::{DownloadError, GitCloneError};
+    use tempfile::tempdir;
+    use versions::Requirement;
+
+    #[test]
+    fn publishes_into_local_registry() {
+        let package = tempdir().unwrap();
+        write(package.path(), "src/lib/Mqtt.lf", "reactor Mqtt {}");
+        write(package.path(), "Lingo.toml", &library_toml("mqtt", "0.1.0"));
+
+        let registry = tempdir().unwrap();
+        let url = registry_url(registry.path().to_str().unwrap()).unwrap();
+
+        let clone: GitCloneAndCheckoutCap =
+            Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())));
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let entry = publish(package.path(), &url, None, true, &clone, &download).unwrap();
+        assert!(registry.path().join("archives/mqtt-0.1.0.tar.gz").exists());
+        assert_eq!(entry.source.source_type, PackageLockSourceType::TARBALL);
+        assert!(publish(package.path(), &url, None, true, &clone, &download).is_err());
+
+        let index = RegistryIndex::open(&url, &clone).unwrap();
+        let requirement = Requirement::new("^0.1.0").unwrap();
+        assert_eq!(
+            index.select("mqtt", &requirement, None).unwrap().checksum,
+            entry.checksum
+        );
+
+        let version = Versioning::new("0.1.0").unwrap();
+        // yanked versions are only used if they are locked, exact requirements don't matter
+        yank("mqtt", &version, &url, false, &clone).unwrap();
+        assert!(index.select("mqtt", &requirement, None).is_err());
+        let exact = Requirement::new("=0.1.0").unwrap();
+        assert!(index.select("mqtt", &exact, None).is_err());
+        assert!(index.select("mqtt", &exact, Some(&version)).is_ok());
+
+        yank("mqtt", &version, &url, true, &clone).unwrap();
+        assert!(index.select("mqtt", &requirement, None).is_ok());
+    }
+}
@@ -0,0 +1,329 @@
+use serde_derive::{Deserialize, Serialize};
+use tempfile::{tempdir, TempDir};
+use url::Url;
+use versions::{Requirement, Versioning};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::package::{
+    deserialize_version, lock::PackageLockSource, serialize_version, tree::PackageDetails,
+};
+use crate::util::errors::LingoError;
+use crate::{GitCloneAndCheckoutCap, GitCloneOptions, GitUrl};
+
+/// environment variable that sets the registry index for packages that don't specify one
+pub const REGISTRY_ENV_VARIABLE: &str = "LINGO_REGISTRY";
+
+/// The Format inside the Lingo.toml under [registry]
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct RegistryFile {
+    /// location of the registry index either a file:// url or a git repository
+    pub index: Url,
+}
+
+/// A single published version of a package inside the registry index
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct IndexEntry {
+    #[serde(
+        serialize_with = "serialize_version",
+        deserialize_with = "deserialize_version"
+    )]
+    pub version: Versioning,
+    /// where the content of this version can be fetched from
+    pub source: PackageLockSource,
+    /// checksum of the package content
+    pub checksum: String,
+    /// checksum of the archive the source points to, only set for published archives
+    #[serde(
+        rename = "archive-checksum",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archive_checksum: Option<String>,
+    /// dependencies of this version, packages without source come from the same registry
+    #[serde(default)]
+    pub dependencies: HashMap<String, PackageDetails>,
+    /// yanked versions are only used by packages that already have them locked
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+}
+
+/// Content of the metadata file `<name>.toml` of a package inside the registry index
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct IndexFile {
+    #[serde(rename = "version", default)]
+    pub versions: Vec<IndexEntry>,
+}
+
+/// A registry index is a directory or git repository that contains one metadata file
+/// per package.
+pub struct RegistryIndex {
+    url: Url,
+    root: PathBuf,
+    /// keeps the checked out git repository alive as long as the index is used
+    _checkout: Option<TempDir>,
+}
+
+impl RegistryIndex {
+    /// opens the index, file:// urls are read in place, everything else is cloned with git
+    pub fn open(url: &Url, clone: &GitCloneAndCheckoutCap) -> anyhow::Result<RegistryIndex> {
+        if url.scheme() == "file" {
+            let root = url
+                .to_file_path()
+                .map_err(|_| LingoError::InvalidRegistry(url.to_string()))?;
+
+            if !root.is_dir() {
+                return Err(LingoError::InvalidRegistry(url.to_string()).into());
+            }
+
+            return Ok(RegistryIndex {
+                url: url.clone(),
+                root,
+                _checkout: None,
+            });
+        }
+
+        let checkout = tempdir()?;
+        clone(
+            GitUrl::from(url.as_str()),
+            checkout.path(),
+            None,
+            GitCloneOptions::default(),
+        )?;
+
+        Ok(RegistryIndex {
+            url: url.clone(),
+            root: checkout.path().to_path_buf(),
+            _checkout: Some(checkout),
+        })
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// location of the metadata file for the given package, names that aren't plain file
+    /// names are rejected so they cannot point outside of the index
+    pub fn metadata_path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(LingoError::InvalidPackageName(name.to_string()).into());
+        }
+
+        Ok(self.root.join(format!("{name}.toml")))
+    }
+
+    /// if the index is a git repository that was cloned for reading it
+    pub fn is_git(&self) -> bool {
+        self._checkout.is_some()
+    }
+
+    /// reads all published versions of a package
+    pub fn package(&self, name: &str) -> anyhow::Result<IndexFile> {
+        let path = self.metadata_path(name)?;
+
+        if !path.exists() {
+            return Err(
+                LingoError::PackageNotInRegistry(name.to_string(), self.url.to_string()).into(),
+            );
+        }
+
+        Ok(toml::from_str::<IndexFile>(&fs::read_to_string(path)?)?)
+    }
+
+    /// overwrites the metadata file of the package
+    pub fn write_package(&self, name: &str, index_file: &IndexFile) -> anyhow::Result<()> {
+        fs::write(self.metadata_path(name)?, toml::to_string(index_file)?)?;
+        Ok(())
+    }
+
+    /// Commits all changes and pushes them to the remote the index was cloned from. Indices
+    /// on the local file system are left for the user to commit.
+    pub fn commit(&self, message: &str) -> anyhow::Result<()> {
+        if !self.is_git() {
+            return Ok(());
+        }
+
+        for args in [
+            vec!["add", "--all"],
+            vec!["commit", "--message", message],
+            vec!["push", "origin", "HEAD"],
+        ] {
+            let output = Command::new("git")
+                .args(&args)
+                .current_dir(&self.root)
+                .output()?;
+
+            if !output.status.success() {
+                return Err(LingoError::RegistryUpdateFailed(
+                    self.url.to_string(),
+                    String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Selects the newest version of the package that satisfies the requirement. Yanked
+    /// versions are only selected if they are the `locked` version.
+    pub fn select(
+        &self,
+        name: &str,
+        requirement: &Requirement,
+        locked: Option<&Versioning>,
+    ) -> anyhow::Result<IndexEntry> {
+        let mut candidates: Vec<IndexEntry> = self
+            .package(name)?
+            .versions
+            .into_iter()
+            .filter(|entry| !entry.yanked || locked == Some(&entry.version))
+            .filter(|entry| requirement.matches(&entry.version))
+            .collect();
+
+        candidates.sort_by(|a, b| a.version.cmp(&b.version));
+
+        candidates
+            .pop()
+            .ok_or(LingoError::NoMatchingVersion(name.to_string(), requirement.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::publish::{publish, registry_url, yank};
+    use crate::util::testing::{library_toml, write};
+    use crate::{DownloadCap, DownloadError, GitCloneError};
+
+    fn publish_version(registry: &Url, version: &str, clone: &GitCloneAndCheckoutCap) {
+        let package = tempdir().unwrap();
+        write(package.path(), "src/lib/Mqtt.lf", "reactor Mqtt {}");
+        write(package.path(), "Lingo.toml", &library_toml("mqtt", version));
+
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+        publish(package.path(), registry, None, true, clone, &download).unwrap();
+    }
+
+    /// file:// index with the versions 0.1.0, 0.1.1 (yanked) and 0.2.0 of mqtt
+    fn registry(clone: &GitCloneAndCheckoutCap) -> (TempDir, Url) {
+        let registry = tempdir().unwrap();
+        let url = registry_url(registry.path().to_str().unwrap()).unwrap();
+        for version in ["0.1.0", "0.1.1", "0.2.0"] {
+            publish_version(&url, version, clone);
+        }
+        yank(
+            "mqtt",
+            &Versioning::new("0.1.1").unwrap(),
+            &url,
+            false,
+            clone,
+        )
+        .unwrap();
+        (registry, url)
+    }
+
+    fn no_clone() -> GitCloneAndCheckoutCap<'static> {
+        Box::new(|_, _, _, _| Err(GitCloneError("no network in tests".to_string())))
+    }
+
+    #[test]
+    fn selects_newest_version_that_is_not_yanked() {
+        let clone = no_clone();
+        let (_registry, url) = registry(&clone);
+        let index = RegistryIndex::open(&url, &clone).unwrap();
+        let select = |requirement: &str, locked: Option<&str>| {
+            index
+                .select(
+                    "mqtt",
+                    &Requirement::new(requirement).unwrap(),
+                    locked.and_then(Versioning::new).as_ref(),
+                )
+                .map(|entry| entry.version.to_string())
+        };
+
+        assert_eq!(select(">=0.1.0", None).unwrap(), "0.2.0");
+        assert_eq!(select("^0.1.0", None).unwrap(), "0.1.0");
+        assert_eq!(select("^0.1.0", Some("0.1.1")).unwrap(), "0.1.1");
+        assert!(select("=0.1.1", None).is_err());
+        assert!(select("=0.3.0", None).is_err());
+        assert!(index
+            .select("sensors", &Requirement::default(), None)
+            .is_err());
+    }
+
+    #[test]
+    fn resolves_packages_through_the_index() {
+        let clone = no_clone();
+        let (_registry, url) = registry(&clone);
+        let download: DownloadCap =
+            Box::new(|_, _| Err(DownloadError("no network in tests".to_string())));
+
+        let mut details = toml::from_str::<PackageDetails>(&format!(
+            "version = \"^0.1.0\"\nregistry = \"{url}\"\n"
+        ))
+        .unwrap();
+
+        let destination = tempdir().unwrap();
+        details
+            .fetch(
+                "mqtt",
+                &destination.path().join("mqtt"),
+                true,
+                &clone,
+                &download,
+            )
+            .unwrap();
+        let lingo_toml = fs::read_to_string(destination.path().join("mqtt/Lingo.toml")).unwrap();
+        assert!(lingo_toml.contains("version = \"0.1.0\""));
+
+        // locked versions are fetched even if they were yanked in the meantime
+        details.locked_version = Versioning::new("0.1.1");
+        details
+            .fetch(
+                "mqtt",
+                &destination.path().join("locked"),
+                true,
+                &clone,
+                &download,
+            )
+            .unwrap();
+        let lingo_toml = fs::read_to_string(destination.path().join("locked/Lingo.toml")).unwrap();
+        assert!(lingo_toml.contains("version = \"0.1.1\""));
+    }
+
+    #[test]
+    fn rejects_names_outside_of_the_index() {
+        let clone = no_clone();
+        let (_registry, url) = registry(&clone);
+        let index = RegistryIndex::open(&url, &clone).unwrap();
+
+        for name in ["../escape", "archives/mqtt", "", "."] {
+            let error = index
+                .select(name, &Requirement::default(), None)
+                .unwrap_err();
+            assert!(
+                matches!(
+                    error.downcast_ref::<LingoError>(),
+                    Some(LingoError::InvalidPackageName(_))
+                ),
+                "{name}: {error}"
+            );
+        }
+        assert!(index
+            .write_package("../escape", &IndexFile::default())
+            .is_err());
+    }
+}
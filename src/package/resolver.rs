@@ -0,0 +1,490 @@
+use indexmap::IndexMap;
+use versions::{Requirement, Versioning};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+/// A concrete version of a package the resolver can choose from
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub version: Versioning,
+    /// requirements this version places on other packages
+    pub dependencies: Vec<(String, Requirement)>,
+}
+
+/// A requirement on a package together with the package that placed it
+#[derive(Clone, Debug)]
+pub struct Constraint {
+    pub required_by: String,
+    /// name of the package that placed the requirement, none for the root package
+    pub package: Option<String>,
+    pub requirement: Requirement,
+}
+
+/// Requirements that made the resolver use a version that takes part in a conflict
+#[derive(Clone, Debug)]
+pub struct Cause {
+    pub package: String,
+    /// the package together with the used version
+    pub used: String,
+    pub constraints: Vec<Constraint>,
+}
+
+/// Explanation why no version of a package can be selected
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub package: String,
+    pub constraints: Vec<Constraint>,
+    pub available: Vec<Versioning>,
+    /// why the versions that placed the constraints are used, down to the root package
+    pub causes: Vec<Cause>,
+}
+
+/// lists who requires which versions of `package`
+fn describe(package: &str, constraints: &[Constraint]) -> String {
+    constraints
+        .iter()
+        .map(|constraint| {
+            format!(
+                "{} requires {} {}",
+                constraint.required_by, package, constraint.requirement
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let reasons = describe(&self.package, &self.constraints);
+
+        let available = if self.available.is_empty() {
+            "none".to_string()
+        } else {
+            self.available
+                .iter()
+                .map(|version| version.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        write!(
+            f,
+            "no version of {} satisfies all requirements: {} (available versions: {})",
+            self.package, reasons, available
+        )?;
+
+        for cause in &self.causes {
+            write!(
+                f,
+                "\n  {} is used because {}",
+                cause.used,
+                describe(&cause.package, &cause.constraints)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A failed attempt to select versions, only changing the versions of the `culprits` can
+/// avoid the `conflict`
+struct Failure {
+    culprits: HashSet<String>,
+    conflict: Box<Conflict>,
+}
+
+/// Backtracking resolver that picks the newest version of every package such that all
+/// requirements across the whole dependency graph are satisfied. Failures remember which
+/// decisions caused them, decisions that played no part are skipped when backtracking.
+struct Resolver<'a> {
+    candidates: &'a HashMap<String, Vec<Candidate>>,
+    /// packages with their selected version in the order they were decided
+    selected: IndexMap<String, Candidate>,
+    /// requirements placed on packages by the root and all selected versions
+    constraints: IndexMap<String, Vec<Constraint>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn available(&self, package: &str) -> Vec<Versioning> {
+        let mut versions: Vec<Versioning> = self
+            .candidates
+            .get(package)
+            .map(|candidates| candidates.iter().map(|c| c.version.clone()).collect())
+            .unwrap_or_default();
+        versions.sort();
+        versions.dedup();
+        versions
+    }
+
+    fn add_constraints(&mut self, package: Option<&str>, required_by: &str, candidate: &Candidate) {
+        for (dependency, requirement) in &candidate.dependencies {
+            self.constraints
+                .entry(dependency.clone())
+                .or_default()
+                .push(Constraint {
+                    required_by: required_by.to_string(),
+                    package: package.map(str::to_string),
+                    requirement: requirement.clone(),
+                });
+        }
+    }
+
+    /// explains why none of the versions of `package` fits the `constraints`
+    fn conflict(&self, package: &str, constraints: Vec<Constraint>) -> Conflict {
+        let mut causes = vec![];
+        let mut visited = HashSet::from([package.to_string()]);
+        let mut queue: Vec<&Constraint> = constraints.iter().collect();
+        let mut index = 0;
+        while let Some(constraint) = queue.get(index) {
+            index += 1;
+            let Some(owner) = &constraint.package else {
+                continue;
+            };
+            if !visited.insert(owner.clone()) {
+                continue;
+            }
+
+            let owner_constraints = self
+                .constraints
+                .get(owner)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            causes.push(Cause {
+                package: owner.clone(),
+                used: constraint.required_by.clone(),
+                constraints: owner_constraints.to_vec(),
+            });
+            queue.extend(owner_constraints);
+        }
+
+        Conflict {
+            package: package.to_string(),
+            available: self.available(package),
+            constraints,
+            causes,
+        }
+    }
+
+    fn remove_constraints(&mut self, candidate: &Candidate) {
+        for (dependency, _) in candidate.dependencies.iter().rev() {
+            if let Some(constraints) = self.constraints.get_mut(dependency) {
+                constraints.pop();
+                if constraints.is_empty() {
+                    self.constraints.shift_remove(dependency);
+                }
+            }
+        }
+    }
+
+    fn solve(&mut self) -> Result<(), Failure> {
+        let package = match self
+            .constraints
+            .keys()
+            .find(|name| !self.selected.contains_key(*name))
+        {
+            Some(name) => name.clone(),
+            None => return Ok(()),
+        };
+
+        let constraints = self.constraints[&package].clone();
+
+        // the packages that placed the requirements limit the versions that can be tried
+        let mut culprits: HashSet<String> = constraints
+            .iter()
+            .filter_map(|constraint| constraint.package.clone())
+            .collect();
+
+        let mut options: Vec<&Candidate> = self
+            .candidates
+            .get(&package)
+            .map(|candidates| {
+                candidates
+                    .iter()
+                    .filter(|candidate| {
+                        constraints
+                            .iter()
+                            .all(|constraint| constraint.requirement.matches(&candidate.version))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // newest versions are tried first
+        options.sort_by(|a, b| b.version.cmp(&a.version));
+
+        // the first conflict found is reported, it involves the newest versions
+        let mut conflict = None;
+
+        for candidate in options {
+            let required_by = format!("{} {}", package, candidate.version);
+
+            // packages that are already decided have to satisfy the requirements of this version
+            let violated = candidate
+                .dependencies
+                .iter()
+                .find(|(dependency, requirement)| {
+                    self.selected
+                        .get(dependency)
+                        .is_some_and(|selected| !requirement.matches(&selected.version))
+                });
+
+            if let Some((dependency, requirement)) = violated {
+                let mut constraints = self.constraints[dependency].clone();
+                constraints.push(Constraint {
+                    required_by,
+                    package: Some(package.clone()),
+                    requirement: requirement.clone(),
+                });
+
+                culprits.insert(dependency.clone());
+                if conflict.is_none() {
+                    conflict = Some(Box::new(self.conflict(dependency, constraints)));
+                }
+                continue;
+            }
+
+            self.add_constraints(Some(&package), &required_by, candidate);
+            self.selected.insert(package.clone(), candidate.clone());
+
+            let failure = match self.solve() {
+                Ok(()) => return Ok(()),
+                Err(failure) => failure,
+            };
+
+            self.selected.shift_remove(&package);
+            self.remove_constraints(candidate);
+
+            // other versions of this package can't avoid a conflict it didn't cause
+            if !failure.culprits.contains(&package) {
+                return Err(failure);
+            }
+
+            culprits.extend(failure.culprits);
+            conflict.get_or_insert(failure.conflict);
+        }
+
+        culprits.remove(&package);
+        Err(Failure {
+            culprits,
+            conflict: conflict.unwrap_or_else(|| Box::new(self.conflict(&package, constraints))),
+        })
+    }
+}
+
+/// Selects one version for every package reachable from the root dependencies. The returned
+/// map contains the chosen candidate for each package.
+pub fn resolve(
+    root: &str,
+    dependencies: &[(String, Requirement)],
+    candidates: &HashMap<String, Vec<Candidate>>,
+) -> Result<IndexMap<String, Candidate>, Conflict> {
+    let mut resolver = Resolver {
+        candidates,
+        selected: IndexMap::new(),
+        constraints: IndexMap::new(),
+    };
+
+    resolver.add_constraints(
+        None,
+        root,
+        &Candidate {
+            version: Versioning::default(),
+            dependencies: dependencies.to_vec(),
+        },
+    );
+
+    match resolver.solve() {
+        Ok(()) => Ok(resolver.selected),
+        Err(failure) => Err(*failure.conflict),
+    }
+}
+
+/// Orders the selected packages so that every package comes after its dependencies.
+/// Cycles are broken in selection order.
+pub fn dependency_order(selected: &IndexMap<String, Candidate>) -> Vec<String> {
+    fn visit(
+        name: &str,
+        selected: &IndexMap<String, Candidate>,
+        visited: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) {
+        let Some(candidate) = selected.get(name) else {
+            return;
+        };
+
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+
+        for (dependency, _) in &candidate.dependencies {
+            visit(dependency, selected, visited, order);
+        }
+
+        order.push(name.to_string());
+    }
+
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+
+    for name in selected.keys() {
+        visit(name, selected, &mut visited, &mut order);
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn candidate(version: &str, dependencies: &[(&str, &str)]) -> Candidate {
+        Candidate {
+            version: Versioning::new(version).unwrap(),
+            dependencies: dependencies
+                .iter()
+                .map(|(name, requirement)| {
+                    (
+                        name.to_string(),
+                        Requirement::from_str(requirement).unwrap(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn picks_newest_compatible_versions() {
+        let candidates = HashMap::from([
+            (
+                "foo".to_string(),
+                vec![
+                    candidate("1.0.0", &[]),
+                    candidate("1.4.0", &[]),
+                    candidate("2.0.0", &[]),
+                ],
+            ),
+            (
+                "bar".to_string(),
+                vec![
+                    candidate("1.0.0", &[("foo", "^1.0.0")]),
+                    candidate("2.0.0", &[("foo", "^2.0.0")]),
+                ],
+            ),
+        ]);
+
+        let selection = resolve(
+            "app",
+            &[
+                ("foo".to_string(), Requirement::from_str(">=1.0.0").unwrap()),
+                ("bar".to_string(), Requirement::from_str("<2.0.0").unwrap()),
+            ],
+            &candidates,
+        )
+        .unwrap();
+
+        assert_eq!(selection["foo"].version.to_string(), "1.4.0");
+        assert_eq!(selection["bar"].version.to_string(), "1.0.0");
+    }
+
+    #[test]
+    fn explains_conflicts() {
+        let candidates = HashMap::from([
+            (
+                "foo".to_string(),
+                vec![candidate("1.0.0", &[]), candidate("2.0.0", &[])],
+            ),
+            (
+                "bar".to_string(),
+                vec![candidate("2.0.0", &[("foo", "^2.0.0")])],
+            ),
+        ]);
+
+        let conflict = resolve(
+            "app",
+            &[
+                ("foo".to_string(), Requirement::from_str("^1.0.0").unwrap()),
+                ("bar".to_string(), Requirement::from_str("^2.0.0").unwrap()),
+            ],
+            &candidates,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            conflict.to_string(),
+            "no version of foo satisfies all requirements: app requires foo ^1.0.0, \
+             bar 2.0.0 requires foo ^2.0.0 (available versions: 1.0.0, 2.0.0)\n  \
+             bar 2.0.0 is used because app requires bar ^2.0.0"
+        );
+    }
+
+    #[test]
+    fn explains_how_conflicting_versions_are_reached() {
+        let candidates = HashMap::from([
+            (
+                "a".to_string(),
+                vec![candidate("2.0.0", &[("b", "^2.0.0")])],
+            ),
+            (
+                "b".to_string(),
+                vec![
+                    candidate("1.0.0", &[("c", "^1.0.0")]),
+                    candidate("2.0.0", &[("c", "^2.0.0")]),
+                ],
+            ),
+            (
+                "c".to_string(),
+                vec![candidate("1.0.0", &[]), candidate("2.0.0", &[])],
+            ),
+        ]);
+
+        let conflict = resolve(
+            "app",
+            &[
+                ("a".to_string(), Requirement::from_str(">=1.0.0").unwrap()),
+                ("c".to_string(), Requirement::from_str("^1.0.0").unwrap()),
+            ],
+            &candidates,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            conflict.to_string(),
+            "no version of c satisfies all requirements: app requires c ^1.0.0, \
+             b 2.0.0 requires c ^2.0.0 (available versions: 1.0.0, 2.0.0)\n  \
+             b 2.0.0 is used because a 2.0.0 requires b ^2.0.0\n  \
+             a 2.0.0 is used because app requires a >=1.0.0"
+        );
+    }
+
+    #[test]
+    fn unrelated_decisions_are_skipped_when_backtracking() {
+        // trying every combination of the unrelated packages would take 3^20 attempts
+        let mut candidates = HashMap::from([
+            ("a".to_string(), vec![candidate("1.0.0", &[])]),
+            (
+                "b".to_string(),
+                vec![candidate("1.0.0", &[("a", "^2.0.0")])],
+            ),
+        ]);
+        let mut dependencies = vec![];
+        for index in 0..20 {
+            let name = format!("p{index:02}");
+            candidates.insert(
+                name.clone(),
+                vec![
+                    candidate("1.0.0", &[]),
+                    candidate("2.0.0", &[]),
+                    candidate("3.0.0", &[]),
+                ],
+            );
+            dependencies.push((name, Requirement::from_str(">=1.0.0").unwrap()));
+        }
+        dependencies.push(("a".to_string(), Requirement::from_str("^1.0.0").unwrap()));
+        dependencies.push(("b".to_string(), Requirement::from_str("^1.0.0").unwrap()));
+
+        let conflict = resolve("app", &dependencies, &candidates).unwrap_err();
+
+        assert_eq!(conflict.package, "a");
+    }
+}
@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use crate::util::errors::LingoError;
+
 pub trait CMakeLoader {
     fn read_file(&mut self, path: &str) -> anyhow::Result<AutoCmakeLoad>;
 }
@@ -56,8 +59,20 @@ pub struct LibraryTargetPropertiesFile {
     sources: Vec<PathBuf>,
 
     /// list of files that should be made available to the user
-    #[serde(rename = "sources", default)]
+    #[serde(rename = "artifacts", default)]
     artifacts: Vec<PathBuf>,
+
+    /// properties that are only added if the feature with the same name is enabled
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    features: HashMap<String, FeatureTargetProperties>,
+}
+
+/// The Format inside the Lingo.toml under [lib.properties.features.<name>]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct FeatureTargetProperties {
+    /// cmake variables that are set before the cmake include
+    #[serde(default)]
+    pub definitions: BTreeMap<String, String>,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -65,16 +80,32 @@ pub struct LibraryTargetProperties {
     /// cmake include only available for C and CPP
     pub cmake_include: AutoCmakeLoad,
 
+    /// cmake file the include above points to, relative to the package root
+    pub cmake_include_file: Option<PathBuf>,
+
     /// files that should be compiled and linked
     pub sources: Vec<PathBuf>,
 
     /// list of files that should be made available to the user
     pub artifacts: Vec<PathBuf>,
+
+    /// properties of the features of the library
+    pub features: HashMap<String, FeatureTargetProperties>,
 }
 
 impl LibraryTargetPropertiesFile {
+    /// files the library declares relative to its package root
+    pub fn files(&self) -> Vec<&PathBuf> {
+        self.cmake_include
+            .iter()
+            .chain(&self.sources)
+            .chain(&self.artifacts)
+            .collect()
+    }
+
     pub fn from(self, name: &String) -> LibraryTargetProperties {
         LibraryTargetProperties {
+            cmake_include_file: self.cmake_include.clone(),
             cmake_include: AutoCmakeLoad(
                 self.cmake_include
                     .map(|cmake_file| {
@@ -84,7 +115,36 @@ impl LibraryTargetPropertiesFile {
             ),
             sources: self.sources,
             artifacts: self.artifacts,
+            features: self.features,
+        }
+    }
+}
+
+impl LibraryTargetProperties {
+    /// properties with the definitions of the `enabled` features added in front of the
+    /// cmake include, the values are escaped so they stay plain strings
+    pub fn with_features(&self, enabled: &[String]) -> anyhow::Result<LibraryTargetProperties> {
+        let mut properties = self.clone();
+        let mut definitions = String::new();
+
+        for feature in enabled.iter().filter_map(|name| self.features.get(name)) {
+            for (name, value) in &feature.definitions {
+                let valid =
+                    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    return Err(LingoError::InvalidDefinition(name.clone()).into());
+                }
+
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('$', "\\$");
+                definitions += &format!("set({name} \"{value}\")\n");
+            }
         }
+
+        properties.cmake_include.0.insert_str(0, &definitions);
+        Ok(properties)
     }
 }
 
@@ -167,3 +227,40 @@ impl AppTargetProperties {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(definitions: &str) -> LibraryTargetProperties {
+        toml::from_str::<LibraryTargetPropertiesFile>(&format!(
+            "cmake-include = \"lib.cmake\"\n\n[features.lttng]\ndefinitions = {{ {definitions} }}\n"
+        ))
+        .unwrap()
+        .from(&"tracing".to_string())
+    }
+
+    #[test]
+    fn definitions_are_escaped() {
+        let properties = library(r#"TRACING_BACKEND = 'a"b\c${HOME}'"#)
+            .with_features(&["lttng".to_string()])
+            .unwrap();
+        assert_eq!(
+            properties.cmake_include.to_string(),
+            "set(TRACING_BACKEND \"a\\\"b\\\\c\\${HOME}\")\ninclude(lfc_include/tracing/lib.cmake)"
+        );
+
+        // features that aren't enabled don't set anything
+        let properties = library("TRACING_BACKEND = 'lttng'")
+            .with_features(&[])
+            .unwrap();
+        assert_eq!(
+            properties.cmake_include.to_string(),
+            "include(lfc_include/tracing/lib.cmake)"
+        );
+
+        assert!(library(r#"'X)\nexecute_process(COMMAND rm' = 'x'"#)
+            .with_features(&["lttng".to_string()])
+            .is_err());
+    }
+}
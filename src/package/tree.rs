@@ -1,9 +1,11 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use url::Url;
 use versions::{Requirement, Versioning};
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+use crate::package::features::DependencyFeatures;
 use crate::package::target_properties::LibraryTargetProperties;
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
@@ -14,11 +16,14 @@ pub enum ProjectSource {
     TarBall(Url),
     #[serde(rename = "path")]
     Path(PathBuf),
+    /// package is looked up inside a registry index, `None` stands for the default registry
+    #[serde(rename = "registry")]
+    Registry(Option<Url>),
     //#[serde(rename = "empty")]
     //Empty,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum GitLock {
     #[serde(rename = "tag")]
     Tag(String),
@@ -38,12 +43,79 @@ pub struct PackageDetails {
         serialize_with = "Requirement::serialize"
     )]
     pub(crate) version: Requirement,
-    #[serde(flatten)]
+    #[serde(
+        flatten,
+        serialize_with = "serialize_source",
+        deserialize_with = "deserialize_source"
+    )]
     pub(crate) mutual_exclusive: ProjectSource,
     #[serde(flatten)]
     pub(crate) git_tag: Option<GitLock>,
+    /// directory inside the git repository or archive that contains the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) subdir: Option<PathBuf>,
+    /// if the submodules of a git dependency are checked out
+    #[serde(default = "checkout_submodules", skip_serializing_if = "is_true")]
+    pub(crate) submodules: bool,
+    /// if the dependency is optional and which of its features are enabled
+    #[serde(flatten)]
+    pub(crate) features: DependencyFeatures,
     #[serde(skip)]
     pub(crate) git_rev: Option<String>,
+    #[serde(skip)]
+    pub(crate) archive_checksum: Option<String>,
+    /// version in the Lingo.lock, registry packages may only use a yanked version if it is locked
+    #[serde(skip)]
+    pub(crate) locked_version: Option<Versioning>,
+}
+
+pub(crate) fn checkout_submodules() -> bool {
+    true
+}
+
+pub(crate) fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// dependencies without an explicit source are resolved through the default registry
+fn deserialize_source<'de, D>(deserializer: D) -> Result<ProjectSource, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        Option::<ProjectSource>::deserialize(deserializer)?
+            .unwrap_or(ProjectSource::Registry(None)),
+    )
+}
+
+fn serialize_source<S>(source: &ProjectSource, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match source {
+        ProjectSource::Registry(None) => serializer.serialize_none(),
+        source => source.serialize(serializer),
+    }
+}
+
+impl PackageDetails {
+    /// packages without an explicit registry are looked up in the given registry
+    pub fn inherit_registry(&mut self, registry: Option<&Url>) {
+        if let (ProjectSource::Registry(own @ None), Some(registry)) =
+            (&mut self.mutual_exclusive, registry)
+        {
+            *own = Some(registry.clone());
+        }
+    }
+
+    /// relative path dependencies are resolved against the directory `root`
+    pub fn anchor(&mut self, root: &Path) {
+        if let ProjectSource::Path(path) = &mut self.mutual_exclusive {
+            if path.is_relative() {
+                *path = root.join(&*path);
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -52,7 +124,7 @@ pub struct DependencyTreeNode {
     pub(crate) name: String,
     /// version specified in the Lingo.toml
     pub(crate) version: Versioning,
-    /// source of this package
+    /// source of this package together with the version requirement the parent placed on it
     pub(crate) package: PackageDetails,
     /// location of where the packed has been cloned to
     pub(crate) location: PathBuf,
@@ -64,6 +136,12 @@ pub struct DependencyTreeNode {
     pub(crate) dependencies: Vec<DependencyTreeNode>,
     /// required dependencies to build this package
     pub(crate) properties: LibraryTargetProperties,
+    /// the `[features]` table of the package
+    pub(crate) feature_table: HashMap<String, Vec<String>>,
+    /// dependencies as they are declared in the Lingo.toml of the package
+    pub(crate) declared: Vec<(String, PackageDetails)>,
+    /// features that are enabled for this package
+    pub(crate) features: Vec<String>,
 }
 
 impl DependencyTreeNode {
@@ -77,9 +155,20 @@ impl DependencyTreeNode {
             hash: self.hash.clone(),
             dependencies: Vec::new(),
             properties: self.properties.clone(),
+            feature_table: self.feature_table.clone(),
+            declared: self.declared.clone(),
+            features: self.features.clone(),
         }
     }
 
+    /// requirements this package places on its direct dependencies
+    pub fn requirements(&self) -> Vec<(String, Requirement)> {
+        self.dependencies
+            .iter()
+            .map(|dependency| (dependency.name.clone(), dependency.package.version.clone()))
+            .collect()
+    }
+
     pub fn aggregate(&self) -> Vec<DependencyTreeNode> {
         let mut aggregator = vec![self.shallow_clone()];
 
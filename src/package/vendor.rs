@@ -0,0 +1,242 @@
+use serde_derive::{Deserialize, Serialize};
+use versions::Versioning;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::package::lock::{DependencyLock, PackageLock, PackageLockSource, PackageLockSourceType};
+use crate::package::management::verify_package_checksum;
+use crate::package::tree::{GitLock, PackageDetails};
+use crate::package::{deserialize_version, serialize_version, ConfigFile};
+use crate::util::hash::Ignore;
+
+/// location of the source replacement config relative to the package root
+pub const VENDOR_CONFIG: &str = ".lingo/config.toml";
+
+/// Package that was copied into the vendor directory
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct VendoredPackage {
+    pub name: String,
+    #[serde(
+        serialize_with = "serialize_version",
+        deserialize_with = "deserialize_version"
+    )]
+    pub version: Versioning,
+    /// source the package is replacing
+    pub source: PackageLockSource,
+    pub checksum: String,
+    /// hash of the archive the package was downloaded as, only set for tarball sources
+    #[serde(
+        rename = "archive-checksum",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub archive_checksum: Option<String>,
+    /// location of the copy relative to the package root
+    pub path: PathBuf,
+}
+
+/// Source replacement config written by `lingo vendor`. Packages listed here are taken from
+/// their vendored copy instead of being fetched from their source.
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct VendorConfig {
+    #[serde(rename = "vendored", default)]
+    pub packages: Vec<VendoredPackage>,
+
+    /// directory the paths of the packages are relative to
+    #[serde(skip)]
+    root: PathBuf,
+}
+
+impl VendorConfig {
+    /// reads the config of the package in `package_root`, it is empty if nothing was vendored
+    pub fn load(package_root: &Path) -> anyhow::Result<VendorConfig> {
+        let path = package_root.join(VENDOR_CONFIG);
+
+        let mut config = if path.exists() {
+            toml::from_str::<VendorConfig>(&fs::read_to_string(&path)?)?
+        } else {
+            VendorConfig::default()
+        };
+
+        config.root = package_root.to_path_buf();
+        Ok(config)
+    }
+
+    pub fn write(&self) -> anyhow::Result<()> {
+        let path = self.root.join(VENDOR_CONFIG);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// location of the vendored copy of the package
+    pub fn path(&self, package: &VendoredPackage) -> PathBuf {
+        self.root.join(&package.path)
+    }
+
+    /// vendored copy of exactly the locked package
+    pub fn locked(&self, lock: &PackageLock) -> Option<&VendoredPackage> {
+        self.packages.iter().find(|package| {
+            package.name == lock.name
+                && package.version == lock.version
+                && package.source == lock.source
+        })
+    }
+
+    /// vendored copy that can be used in place of fetching the package from its source
+    pub fn replacement(&self, name: &str, details: &PackageDetails) -> Option<&VendoredPackage> {
+        let source = PackageLockSource::from(details);
+
+        self.packages.iter().find(|package| {
+            let same_revision = match &details.git_tag {
+                Some(GitLock::Rev(rev)) => package.source.rev.as_ref() == Some(rev),
+                _ => true,
+            };
+
+            package.name == name
+                && package.source.source_type == source.source_type
+                && package.source.uri == source.uri
+                && package.source.options() == source.options()
+                && details.version.matches(&package.version)
+                && same_revision
+        })
+    }
+}
+
+/// copies the package without the files that aren't part of its checksum
+fn copy_package(source: &Path, destination: &Path) -> anyhow::Result<()> {
+    let lingo_toml_text = fs::read_to_string(source.join("Lingo.toml"))?;
+    let config_file = toml::from_str::<ConfigFile>(&lingo_toml_text)?;
+    let ignore = Ignore::new(&config_file.package.exclude)?;
+
+    copy_filtered(source, destination, Path::new(""), &ignore)?;
+    Ok(())
+}
+
+fn copy_filtered(
+    root: &Path,
+    destination: &Path,
+    relative: &Path,
+    ignore: &Ignore,
+) -> io::Result<()> {
+    fs::create_dir_all(destination.join(relative))?;
+
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let is_dir = entry.file_type()?.is_dir();
+
+        if ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            copy_filtered(root, destination, &path, ignore)?;
+        } else {
+            fs::copy(entry.path(), destination.join(&path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies every locked package from `include_folder` into `directory`/<name>-<version> and
+/// returns the config that makes future builds use these copies. Path dependencies are
+/// already part of the repository and are not vendored.
+pub fn vendor(
+    lock: &DependencyLock,
+    include_folder: &Path,
+    package_root: &Path,
+    directory: &Path,
+) -> anyhow::Result<VendorConfig> {
+    let previous = VendorConfig::load(package_root)?;
+    let mut config = VendorConfig {
+        packages: vec![],
+        root: package_root.to_path_buf(),
+    };
+
+    for (name, package) in &lock.dependencies {
+        if package.source.source_type == PackageLockSourceType::PATH {
+            continue;
+        }
+
+        let path = directory.join(format!("{}-{}", name, package.version));
+        let destination = package_root.join(&path);
+        if destination.exists() {
+            fs::remove_dir_all(&destination)?;
+        }
+        copy_package(&include_folder.join(name), &destination)?;
+
+        verify_package_checksum(name, &destination, &package.checksum)?;
+
+        config.packages.push(VendoredPackage {
+            name: name.clone(),
+            version: package.version.clone(),
+            source: package.source.clone(),
+            checksum: package.checksum.clone(),
+            archive_checksum: package.archive_checksum.clone(),
+            path,
+        });
+    }
+
+    // copies that were vendored before but aren't locked anymore are removed
+    for stale in &previous.packages {
+        let vendored = config
+            .packages
+            .iter()
+            .any(|package| package.path == stale.path);
+        let stale_path = previous.path(stale);
+        if !vendored && stale_path.exists() {
+            fs::remove_dir_all(stale_path)?;
+        }
+    }
+
+    config.write()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::management::package_checksum;
+    use crate::util::testing::{library_toml, write};
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    #[test]
+    fn vendored_packages_replace_their_source() {
+        let root = tempdir().unwrap();
+        let include_folder = root.path().join("build/lfc_include");
+        let package = include_folder.join("lib");
+        write(&package, ".git/HEAD", "ref: refs/heads/main");
+        write(&package, "Lingo.toml", &library_toml("lib", "0.1.0"));
+
+        let source = PackageLockSource::from_str("git+https://example.org/lib.git#abc")
+            .unwrap_or_else(|_| panic!("cannot parse source"));
+        let mut lock = DependencyLock::default();
+        lock.dependencies.insert(
+            "lib".to_string(),
+            PackageLock {
+                name: "lib".to_string(),
+                version: Versioning::new("0.1.0").unwrap(),
+                source,
+                checksum: package_checksum(&package).unwrap(),
+                archive_checksum: None,
+                dependencies: vec![],
+                location: None,
+                cmake_include: None,
+                features: vec![],
+            },
+        );
+
+        vendor(&lock, &include_folder, root.path(), Path::new("vendor")).unwrap();
+
+        let config = VendorConfig::load(root.path()).unwrap();
+        let vendored = config.locked(&lock.dependencies["lib"]).unwrap();
+        assert_eq!(config.path(vendored), root.path().join("vendor/lib-0.1.0"));
+        assert!(config.path(vendored).join("Lingo.toml").exists());
+        assert!(!config.path(vendored).join(".git").exists());
+    }
+}
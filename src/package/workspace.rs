@@ -0,0 +1,424 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde_derive::{Deserialize, Serialize};
+use versions::Versioning;
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use crate::package::lock::PackageLockSource;
+use crate::package::patch::Patches;
+use crate::package::registry::RegistryFile;
+use crate::package::tree::{PackageDetails, ProjectSource};
+use crate::package::{default_features, Config, ConfigFile, PackageDescription, OUTPUT_DIRECTORY};
+use crate::util::errors::LingoError;
+use crate::FsReadCapability;
+
+/// The Format inside the Lingo.toml under [workspace]
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct WorkspaceFile {
+    /// glob patterns of the member directories relative to the workspace root
+    pub members: Vec<String>,
+    /// glob patterns of directories that are not members even if they match `members`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+/// Lingo.toml at the root of a workspace, the workspace itself is no package
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct WorkspaceConfigFile {
+    pub workspace: WorkspaceFile,
+
+    /// registry for members that don't configure their own
+    pub registry: Option<RegistryFile>,
+
+    /// sources that replace the ones of dependencies anywhere in the workspace
+    #[serde(default, skip_serializing_if = "Patches::is_empty")]
+    pub patch: Patches,
+}
+
+/// Package of a workspace together with its own dependencies
+#[derive(Clone, Debug)]
+pub struct WorkspaceMember {
+    pub name: String,
+    /// absolute path to the directory with the Lingo.toml of the member
+    pub path: PathBuf,
+    /// path dependencies are relative to the workspace root
+    pub dependencies: HashMap<String, PackageDetails>,
+    /// dependencies that are only available to the examples of the member and `lingo test`
+    pub dev_dependencies: HashMap<String, PackageDetails>,
+}
+
+/// checks if the Lingo.toml at `path` has a `[workspace]` table
+pub fn is_workspace(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| toml::from_str::<toml::Table>(&text).ok())
+        .is_some_and(|table| table.contains_key("workspace"))
+}
+
+/// Looks for the root of the workspace the package with the Lingo.toml at `lingo_toml` belongs
+/// to, starting with the package itself. Returns the Lingo.toml of the workspace root.
+pub fn find_workspace(lingo_toml: &Path) -> Option<PathBuf> {
+    let package = lingo_toml.parent()?;
+
+    for directory in package.ancestors() {
+        let candidate = directory.join("Lingo.toml");
+        if !is_workspace(&candidate) {
+            continue;
+        }
+
+        let members =
+            WorkspaceConfigFile::from(&candidate, Box::new(|path| fs::read_to_string(path)))
+                .and_then(|workspace| workspace.member_paths(directory).map_err(io::Error::other))
+                .map_err(|e| log::warn!("ignoring workspace {}: {e}", candidate.display()))
+                .ok()?;
+
+        if directory == package
+            || members
+                .iter()
+                .any(|member| directory.join(member) == package)
+        {
+            return Some(candidate);
+        }
+    }
+
+    None
+}
+
+/// resolves `.` and `..` without touching the file system
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(builder.build()?)
+}
+
+fn collect_directories(
+    root: &Path,
+    relative: &Path,
+    directories: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let name = entry.file_name();
+
+        // build output and hidden directories never contain members
+        if !entry.file_type()?.is_dir()
+            || name == OUTPUT_DIRECTORY
+            || name.to_string_lossy().starts_with('.')
+        {
+            continue;
+        }
+
+        let path = relative.join(name);
+        collect_directories(root, &path, directories)?;
+        directories.push(path);
+    }
+    Ok(())
+}
+
+/// two declarations of the same dependency can only be locked together if they agree on the source
+pub(crate) fn same_source(a: &PackageDetails, b: &PackageDetails) -> bool {
+    PackageLockSource::from(a) == PackageLockSource::from(b) && a.git_tag == b.git_tag
+}
+
+/// two definitions of a feature agree if they enable the same things in any order
+fn same_features(a: &[String], b: &[String]) -> bool {
+    a.iter().collect::<BTreeSet<_>>() == b.iter().collect::<BTreeSet<_>>()
+}
+
+impl WorkspaceConfigFile {
+    pub fn from(path: &Path, fsr: FsReadCapability) -> io::Result<WorkspaceConfigFile> {
+        let contents = fsr(path);
+        contents.and_then(|contents| {
+            toml::from_str(&contents).map_err(|e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("failed to convert string to toml: {}", e),
+                )
+            })
+        })
+    }
+
+    /// Directories of the members relative to the workspace `root`, sorted. Patterns match
+    /// directories with a Lingo.toml, members without wildcards have to exist.
+    pub fn member_paths(&self, root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let members = glob_set(&self.workspace.members)?;
+        let exclude = glob_set(&self.workspace.exclude)?;
+
+        for member in &self.workspace.members {
+            let literal = !member.contains(['*', '?', '[', '{']);
+            if literal && !root.join(member).join("Lingo.toml").is_file() {
+                return Err(LingoError::WorkspaceMemberNotFound(member.clone()).into());
+            }
+        }
+
+        let mut directories = Vec::new();
+        collect_directories(root, Path::new(""), &mut directories)?;
+
+        let mut paths: Vec<PathBuf> = directories
+            .into_iter()
+            .filter(|path| members.is_match(path) && !exclude.is_match(path))
+            .filter(|path| root.join(path).join("Lingo.toml").is_file())
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Loads all members and combines them into one config for the directory `root`. The
+    /// apps of all members are built into the output directory of the workspace.
+    pub fn to_config(self, root: &Path, fsr: FsReadCapability) -> anyhow::Result<Config> {
+        let mut config = Config {
+            root: root.to_path_buf(),
+            package: PackageDescription {
+                name: root
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or("workspace".to_string()),
+                version: Versioning::default(),
+                authors: None,
+                website: None,
+                license: None,
+                description: None,
+                exclude: vec![],
+                include: vec![],
+            },
+            apps: vec![],
+            library: None,
+            dependencies: HashMap::new(),
+            dev_dependencies: HashMap::new(),
+            app_dependencies: vec![],
+            features: HashMap::new(),
+            requested_features: vec![],
+            registry: None,
+            patches: self.patch.clone(),
+            members: vec![],
+        };
+
+        let mut declared = HashMap::<String, (String, PackageDetails)>::new();
+        let mut defined = HashMap::<String, String>::new();
+
+        for relative in self.member_paths(root)? {
+            let path = root.join(&relative);
+            let mut member_file =
+                ConfigFile::from(&path.join("Lingo.toml"), Box::new(|path| fsr(path)))?;
+            if member_file.registry.is_none() {
+                member_file.registry = self.registry.clone();
+            }
+            if !member_file.patch.is_empty() {
+                log::warn!(
+                    "ignoring [patch] of workspace member {}, only the workspace root is patched",
+                    member_file.package.name
+                );
+            }
+
+            let mut member = member_file.to_config(&path);
+
+            // dependencies are resolved from the workspace root
+            for details in member
+                .dependencies
+                .values_mut()
+                .chain(member.dev_dependencies.values_mut())
+                .chain(
+                    member
+                        .apps
+                        .iter_mut()
+                        .flat_map(|app| app.dependencies.values_mut()),
+                )
+            {
+                if let ProjectSource::Path(dependency) = &mut details.mutual_exclusive {
+                    if dependency.is_relative() {
+                        *dependency = normalize(&relative.join(&*dependency));
+                    }
+                }
+            }
+
+            for (name, details) in member
+                .dependencies
+                .iter()
+                .chain(&member.dev_dependencies)
+                .chain(member.apps.iter().flat_map(|app| &app.dependencies))
+            {
+                match declared.get(name) {
+                    Some((other, previous)) if !same_source(previous, details) => {
+                        return Err(LingoError::ConflictingWorkspaceDependency(
+                            name.clone(),
+                            other.clone(),
+                            member.package.name.clone(),
+                        )
+                        .into());
+                    }
+                    Some(_) => {}
+                    None => {
+                        declared
+                            .insert(name.clone(), (member.package.name.clone(), details.clone()));
+                    }
+                }
+            }
+
+            for mut app in member.apps {
+                config.app_dependencies.extend(app.dependencies.clone());
+                app.output_root = root.join(OUTPUT_DIRECTORY);
+                config.apps.push(app);
+            }
+
+            // features are selected for the whole workspace, a name can only mean one thing
+            for (name, enables) in member.features {
+                match config.features.get(&name) {
+                    Some(previous) if !same_features(previous, &enables) => {
+                        return Err(LingoError::ConflictingWorkspaceFeature(
+                            name.clone(),
+                            defined[&name].clone(),
+                            member.package.name.clone(),
+                        )
+                        .into());
+                    }
+                    Some(_) => {}
+                    None => {
+                        defined.insert(name.clone(), member.package.name.clone());
+                        config.features.insert(name, enables);
+                    }
+                }
+            }
+            config.registry = config.registry.or(member.registry);
+            config.members.push(WorkspaceMember {
+                name: member.package.name,
+                path,
+                dependencies: member.dependencies,
+                dev_dependencies: member.dev_dependencies,
+            });
+        }
+
+        config.requested_features = default_features(&config.features);
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::write;
+    use tempfile::tempdir;
+
+    fn member(name: &str, dependencies: &str) -> String {
+        format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n\n[[app]]\nname = \"{name}\"\ntarget = \"Cpp\"\n\n[app.properties]\n\n[dependencies]\n{dependencies}")
+    }
+
+    #[test]
+    fn members_are_found_through_globs() {
+        let root = tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        write(
+            &root,
+            "Lingo.toml",
+            "[workspace]\nmembers = [\"sim/*\", \"libs/*\"]\nexclude = [\"sim/old\"]\n",
+        );
+        write(
+            &root,
+            "sim/plant/Lingo.toml",
+            &member(
+                "plant",
+                "motors = { version = \">=0.1\", path = \"../../libs/motors\" }\n",
+            ),
+        );
+        write(&root, "sim/old/Lingo.toml", &member("old", ""));
+        write(&root, "sim/notes/README.md", "no member");
+        write(&root, "libs/motors/Lingo.toml", &member("motors", ""));
+
+        let workspace_file = root.join("Lingo.toml");
+        let workspace =
+            WorkspaceConfigFile::from(&workspace_file, Box::new(|path| fs::read_to_string(path)))
+                .unwrap();
+        assert_eq!(
+            workspace.member_paths(&root).unwrap(),
+            ["libs/motors", "sim/plant"].map(PathBuf::from)
+        );
+
+        assert_eq!(
+            find_workspace(&root.join("sim/plant/Lingo.toml")),
+            Some(workspace_file.clone())
+        );
+        assert_eq!(find_workspace(&root.join("sim/old/Lingo.toml")), None);
+
+        let config = workspace
+            .to_config(&root, Box::new(|path| fs::read_to_string(path)))
+            .unwrap();
+        assert_eq!(config.members.len(), 2);
+        assert_eq!(config.apps.len(), 2);
+        assert!(config
+            .apps
+            .iter()
+            .all(|app| app.output_root == root.join(OUTPUT_DIRECTORY)));
+
+        let plant = &config.members[1].dependencies["motors"];
+        assert!(
+            matches!(&plant.mutual_exclusive, ProjectSource::Path(path) if path == Path::new("libs/motors"))
+        );
+    }
+
+    #[test]
+    fn members_have_to_agree_on_features() {
+        let root = tempdir().unwrap();
+        let root = fs::canonicalize(root.path()).unwrap();
+        write(&root, "Lingo.toml", "[workspace]\nmembers = [\"*\"]\n");
+        write(
+            &root,
+            "plant/Lingo.toml",
+            &(member("plant", "")
+                + "\n[features]\ndefault = [\"viz\", \"logging\"]\nviz = []\nlogging = []\n"),
+        );
+        write(
+            &root,
+            "controller/Lingo.toml",
+            &(member("controller", "")
+                + "\n[features]\ndefault = [\"logging\", \"viz\"]\nlogging = []\n"),
+        );
+
+        let load = || {
+            WorkspaceConfigFile::from(
+                &root.join("Lingo.toml"),
+                Box::new(|path| fs::read_to_string(path)),
+            )
+            .unwrap()
+            .to_config(&root, Box::new(|path| fs::read_to_string(path)))
+        };
+        let config = load().unwrap();
+        assert_eq!(config.features.len(), 3);
+        assert_eq!(config.requested_features, ["default"]);
+
+        write(
+            &root,
+            "controller/Lingo.toml",
+            &(member("controller", "") + "\n[features]\ndefault = [\"logging\"]\nlogging = []\n"),
+        );
+        let error = load().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LingoError>(),
+            Some(LingoError::ConflictingWorkspaceFeature(name, first, second))
+                if name == "default" && first == "controller" && second == "plant"
+        ));
+    }
+}
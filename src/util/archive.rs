@@ -0,0 +1,314 @@
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::path::{Component, Path, PathBuf};
+use xz2::read::XzDecoder;
+
+/// archive formats that can be used as package sources
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// determines the archive format from the file name or url path
+    pub fn from_name(name: &str) -> Option<ArchiveFormat> {
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(ArchiveFormat::TarXz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extracts the archive into the destination folder. Release archives usually wrap their
+/// content into a single top-level directory (e.g. `mqtt-1.2.0/`), if this is the case
+/// this directory is stripped so the Lingo.toml ends up directly inside `destination`.
+pub fn extract(archive: &Path, format: ArchiveFormat, destination: &Path) -> io::Result<()> {
+    let entries = list_entries(archive, format)?;
+    let strip = common_top_level_directory(&entries);
+
+    fs::create_dir_all(destination)?;
+
+    match format {
+        ArchiveFormat::TarGz => unpack_tar(
+            tar::Archive::new(GzDecoder::new(File::open(archive)?)),
+            strip,
+            destination,
+        ),
+        ArchiveFormat::TarXz => unpack_tar(
+            tar::Archive::new(XzDecoder::new(File::open(archive)?)),
+            strip,
+            destination,
+        ),
+        ArchiveFormat::Zip => unpack_zip(archive, strip, destination),
+    }
+}
+
+fn list_entries(archive: &Path, format: ArchiveFormat) -> io::Result<Vec<PathBuf>> {
+    fn tar_entries<R: Read>(mut archive: tar::Archive<R>) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            if !is_metadata(&entry) {
+                paths.push(entry.path()?.to_path_buf());
+            }
+        }
+        Ok(paths)
+    }
+
+    match format {
+        ArchiveFormat::TarGz => {
+            tar_entries(tar::Archive::new(GzDecoder::new(File::open(archive)?)))
+        }
+        ArchiveFormat::TarXz => {
+            tar_entries(tar::Archive::new(XzDecoder::new(File::open(archive)?)))
+        }
+        ArchiveFormat::Zip => {
+            let zip = zip::ZipArchive::new(File::open(archive)?).map_err(zip_error)?;
+            Ok(zip.file_names().map(PathBuf::from).collect())
+        }
+    }
+}
+
+/// checks if all entries of the archive live inside one common top-level directory
+fn common_top_level_directory(entries: &[PathBuf]) -> bool {
+    let mut top_level = None;
+    let mut nested = false;
+
+    for entry in entries {
+        let mut components = entry
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir));
+
+        match (components.next(), top_level) {
+            (Some(Component::Normal(first)), None) => top_level = Some(first),
+            (Some(Component::Normal(first)), Some(existing)) if first == existing => {}
+            _ => return false,
+        }
+
+        nested |= components.next().is_some();
+    }
+
+    nested
+}
+
+/// removes the top-level directory if requested and rejects paths that escape the destination
+fn sanitize(path: &Path, strip: bool) -> io::Result<Option<PathBuf>> {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => result.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("archive contains invalid path {}", path.display()),
+                ))
+            }
+        }
+    }
+
+    if strip {
+        result = result.components().skip(1).collect();
+    }
+
+    if result.as_os_str().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(result))
+    }
+}
+
+/// pax headers like the one created by `git archive` don't belong to the content
+fn is_metadata<R: Read>(entry: &tar::Entry<R>) -> bool {
+    matches!(
+        entry.header().entry_type(),
+        tar::EntryType::XGlobalHeader | tar::EntryType::XHeader
+    )
+}
+
+fn unpack_tar<R: Read>(
+    mut archive: tar::Archive<R>,
+    strip: bool,
+    destination: &Path,
+) -> io::Result<()> {
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if is_metadata(&entry) {
+            continue;
+        }
+        let path = entry.path()?.to_path_buf();
+
+        // links could point outside of the destination and later entries would be written
+        // through them, packages therefore must not contain any
+        if matches!(
+            entry.header().entry_type(),
+            tar::EntryType::Symlink | tar::EntryType::Link
+        ) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("archive contains link {}", path.display()),
+            ));
+        }
+
+        if let Some(relative) = sanitize(&path, strip)? {
+            let target = destination.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&target)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn unpack_zip(archive: &Path, strip: bool, destination: &Path) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(zip_error)?;
+
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index).map_err(zip_error)?;
+        let path = file.enclosed_name().ok_or(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("archive contains invalid path {}", file.name()),
+        ))?;
+
+        if let Some(relative) = sanitize(&path, strip)? {
+            let target = destination.join(relative);
+            if file.is_dir() {
+                fs::create_dir_all(&target)?;
+            } else {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut file, &mut File::create(&target)?)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn zip_error(error: zip::result::ZipError) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    /// builds a tar header, the name is written raw so invalid paths can be tested as well
+    fn header(name: &str, entry_type: tar::EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        header.set_size(size);
+        header
+    }
+
+    fn tar_gz(path: &Path, build: impl FnOnce(&mut tar::Builder<GzEncoder<File>>)) {
+        let encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        build(&mut builder);
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn append_file(builder: &mut tar::Builder<GzEncoder<File>>, name: &str, content: &str) {
+        let mut header = header(name, tar::EntryType::Regular, content.len() as u64);
+        header.set_cksum();
+        builder.append(&header, content.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn top_level_directory_is_stripped() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("mqtt-1.2.0.tar.gz");
+        tar_gz(&archive, |builder| {
+            append_file(builder, "mqtt-1.2.0/Lingo.toml", "[package]");
+            append_file(builder, "mqtt-1.2.0/src/lib/Mqtt.lf", "reactor Mqtt {}");
+        });
+
+        let destination = dir.path().join("out");
+        extract(&archive, ArchiveFormat::TarGz, &destination).unwrap();
+        assert_eq!(
+            fs::read_to_string(destination.join("Lingo.toml")).unwrap(),
+            "[package]"
+        );
+        assert!(destination.join("src/lib/Mqtt.lf").is_file());
+        assert!(!destination.join("mqtt-1.2.0").exists());
+    }
+
+    #[test]
+    fn escaping_paths_are_rejected() {
+        for name in ["../evil.txt", "/tmp/evil.txt", "pkg/../../evil.txt"] {
+            let dir = tempdir().unwrap();
+            let archive = dir.path().join("evil.tar.gz");
+            tar_gz(&archive, |builder| append_file(builder, name, "evil"));
+
+            let destination = dir.path().join("out");
+            let error = extract(&archive, ArchiveFormat::TarGz, &destination).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{name}");
+            assert!(!dir.path().join("evil.txt").exists());
+        }
+    }
+
+    #[test]
+    fn links_are_rejected() {
+        let dir = tempdir().unwrap();
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(&outside).unwrap();
+
+        let archive = dir.path().join("link.tar.gz");
+        tar_gz(&archive, |builder| {
+            let mut link = header("pkg/a", tar::EntryType::Symlink, 0);
+            link.set_link_name(&outside).unwrap();
+            link.set_cksum();
+            builder.append(&link, io::empty()).unwrap();
+            append_file(builder, "pkg/a/x", "evil");
+        });
+
+        let destination = dir.path().join("out");
+        let error = extract(&archive, ArchiveFormat::TarGz, &destination).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(!outside.join("x").exists());
+        assert!(fs::symlink_metadata(destination.join("a")).is_err());
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("mqtt.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("mqtt/", options).unwrap();
+        zip.start_file("mqtt/Lingo.toml", options).unwrap();
+        zip.write_all(b"[package]").unwrap();
+        zip.start_file("mqtt/src/lib/Mqtt.lf", options).unwrap();
+        zip.write_all(b"reactor Mqtt {}").unwrap();
+        zip.finish().unwrap();
+
+        let destination = dir.path().join("out");
+        extract(&archive, ArchiveFormat::Zip, &destination).unwrap();
+        assert_eq!(
+            fs::read_to_string(destination.join("Lingo.toml")).unwrap(),
+            "[package]"
+        );
+        assert_eq!(
+            fs::read_to_string(destination.join("src/lib/Mqtt.lf")).unwrap(),
+            "reactor Mqtt {}"
+        );
+    }
+}
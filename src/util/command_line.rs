@@ -2,13 +2,14 @@ use std::io::{self, Write};
 use std::process::{Command, ExitStatus, Stdio};
 
 use crate::util::errors::{BuildResult, LingoError};
-use crossbeam::thread;
 
+#[allow(dead_code)] // unused as long as the output of child processes isn't captured
 struct TeeWriter<'a, W0: Write, W1: Write> {
     w0: &'a mut W0,
     w1: &'a mut W1,
 }
 
+#[allow(dead_code)]
 impl<'a, W0: Write, W1: Write> TeeWriter<'a, W0, W1> {
     fn new(w0: &'a mut W0, w1: &'a mut W1) -> Self {
         Self { w0, w1 }
@@ -13,11 +13,45 @@ pub enum LingoError {
     Shared(Arc<AnyError>),
     CommandFailed(Command, ExitStatus),
     UnknownAppNames(Vec<String>),
+    UnknownDependencyNames(Vec<String>),
     InvalidProjectLocation(PathBuf),
     UseWestBuildToBuildApp,
     InvalidMainReactor,
     NoLibraryInLingoToml(String),
     LingoVersionMismatch(String),
+    UnsupportedArchiveFormat(String),
+    ArchiveChecksumMismatch(String, String, String),
+    NoRegistryConfigured(String),
+    InvalidRegistry(String),
+    PackageNotInRegistry(String, String),
+    InvalidPackageName(String),
+    NoMatchingVersion(String, String),
+    ChecksumMismatch(String, String, String),
+    DependencyResolution(String),
+    DependencyCycle(Vec<String>),
+    LockFileOutdated(String),
+    NetworkAccessOffline(String),
+    PackagesNotAvailableOffline(Vec<String>),
+    InvalidDependencyTable(String),
+    LockFileRequired(String),
+    InvalidLockFile(String, String),
+    LockFileTooNew(i64, i64),
+    UnknownFeature(String, String),
+    UnknownFeatures(Vec<String>),
+    InvalidDefinition(String),
+    MissingPackageFiles(Vec<String>),
+    PathDependenciesInPackage(Vec<String>),
+    VersionAlreadyPublished(String, String),
+    DirtyWorkingTree(Vec<String>),
+    ArchiveLocationRequired(String),
+    RegistryUpdateFailed(String, String),
+    NoRegistryGiven,
+    InvalidSubdirectory(String, PathBuf),
+    WorkspaceMemberNotFound(String),
+    UnknownWorkspaceMembers(Vec<String>),
+    ConflictingWorkspaceDependency(String, String, String),
+    ConflictingWorkspaceFeature(String, String, String),
+    ConflictingDependency(String),
 }
 
 impl Display for LingoError {
@@ -32,6 +66,9 @@ impl Display for LingoError {
             LingoError::UnknownAppNames(names) => {
                 write!(f, "Unknown app names: {}", names.join(", "))
             }
+            LingoError::UnknownDependencyNames(names) => {
+                write!(f, "Unknown dependency names: {}", names.join(", "))
+            }
             LingoError::InvalidProjectLocation(path) => {
                 write!(f, "Cannot initialize repository in {}", path.display())
             }
@@ -56,6 +93,180 @@ impl Display for LingoError {
                     "Version specified in Lingo.toml doesn't match the version in the location {message}"
                 )
             }
+            LingoError::UnsupportedArchiveFormat(url) => {
+                write!(
+                    f,
+                    "Cannot fetch {url} only .tar.gz, .tar.xz and .zip archives are supported"
+                )
+            }
+            LingoError::ArchiveChecksumMismatch(url, expected, actual) => {
+                write!(
+                    f,
+                    "Checksum of archive {url} doesn't match the lock file expected {expected} got {actual}"
+                )
+            }
+            LingoError::NoRegistryConfigured(name) => {
+                write!(
+                    f,
+                    "Dependency {name} has no source and no registry is configured, set [registry] index in Lingo.toml or LINGO_REGISTRY"
+                )
+            }
+            LingoError::InvalidRegistry(url) => {
+                write!(f, "Cannot open registry index {url}")
+            }
+            LingoError::PackageNotInRegistry(name, url) => {
+                write!(f, "Package {name} cannot be found in registry {url}")
+            }
+            LingoError::InvalidPackageName(name) => {
+                write!(
+                    f,
+                    "Invalid package name {name:?}, only letters, digits, - and _ are allowed"
+                )
+            }
+            LingoError::NoMatchingVersion(name, requirement) => {
+                write!(
+                    f,
+                    "No version of {name} matches the requirement {requirement}"
+                )
+            }
+            LingoError::ChecksumMismatch(name, expected, actual) => {
+                write!(
+                    f,
+                    "Checksum of package {name} doesn't match expected {expected} got {actual}"
+                )
+            }
+            LingoError::DependencyResolution(reason) => {
+                write!(f, "Cannot resolve dependencies: {reason}")
+            }
+            LingoError::DependencyCycle(cycle) => {
+                write!(
+                    f,
+                    "Cyclic dependency between packages: {}",
+                    cycle.join(" -> ")
+                )
+            }
+            LingoError::LockFileOutdated(reason) => {
+                write!(
+                    f,
+                    "Lingo.lock needs to be updated but --locked or --frozen was passed: {reason}"
+                )
+            }
+            LingoError::NetworkAccessOffline(source) => {
+                write!(f, "Cannot fetch {source} in offline mode")
+            }
+            LingoError::PackagesNotAvailableOffline(names) => {
+                write!(
+                    f,
+                    "The following packages are neither cached nor vendored and cannot be fetched offline: {}",
+                    names.join(", ")
+                )
+            }
+            LingoError::InvalidDependencyTable(path) => {
+                write!(f, "[dependencies] in {path} is not a table")
+            }
+            LingoError::LockFileRequired(reason) => {
+                write!(
+                    f,
+                    "Lingo.lock is not up to date, run `lingo update` first: {reason}"
+                )
+            }
+            LingoError::InvalidLockFile(path, reason) => {
+                write!(
+                    f,
+                    "Cannot read {path}: {reason}. Remove it and run `lingo update` to create a new one"
+                )
+            }
+            LingoError::LockFileTooNew(found, supported) => {
+                write!(
+                    f,
+                    "Lingo.lock has format version {found} but this lingo only reads up to version {supported}, please update lingo"
+                )
+            }
+            LingoError::UnknownFeature(package, feature) => {
+                write!(
+                    f,
+                    "{package} has no feature, optional dependency or dependency named after {feature}"
+                )
+            }
+            LingoError::UnknownFeatures(features) => {
+                write!(f, "Unknown features: {}", features.join(", "))
+            }
+            LingoError::InvalidDefinition(name) => {
+                write!(
+                    f,
+                    "Invalid cmake variable {name:?}, only letters, digits and _ are allowed"
+                )
+            }
+            LingoError::MissingPackageFiles(paths) => {
+                write!(
+                    f,
+                    "Files declared by the library do not exist: {}",
+                    paths.join(", ")
+                )
+            }
+            LingoError::PathDependenciesInPackage(names) => {
+                write!(
+                    f,
+                    "Packaged libraries cannot have path dependencies: {}",
+                    names.join(", ")
+                )
+            }
+            LingoError::VersionAlreadyPublished(name, version) => {
+                write!(f, "Version {version} of {name} is already published")
+            }
+            LingoError::DirtyWorkingTree(paths) => {
+                write!(
+                    f,
+                    "The working tree has uncommitted changes, commit them or pass --allow-dirty: {}",
+                    paths.join(", ")
+                )
+            }
+            LingoError::ArchiveLocationRequired(registry) => {
+                write!(
+                    f,
+                    "Registry {registry} cannot host archives, pass --archive-url with the location the archive is uploaded to"
+                )
+            }
+            LingoError::RegistryUpdateFailed(registry, reason) => {
+                write!(f, "Cannot update registry {registry}: {reason}")
+            }
+            LingoError::NoRegistryGiven => {
+                write!(
+                    f,
+                    "No registry given, pass --registry or set [registry] index in Lingo.toml or LINGO_REGISTRY"
+                )
+            }
+            LingoError::InvalidSubdirectory(name, subdir) => {
+                write!(
+                    f,
+                    "Dependency {name} has no package in subdir {}, it has to be a relative path to a directory with a Lingo.toml inside a git repository or archive",
+                    subdir.display()
+                )
+            }
+            LingoError::WorkspaceMemberNotFound(member) => {
+                write!(f, "Workspace member {member} has no Lingo.toml")
+            }
+            LingoError::UnknownWorkspaceMembers(names) => {
+                write!(f, "Unknown workspace members: {}", names.join(", "))
+            }
+            LingoError::ConflictingWorkspaceDependency(name, first, second) => {
+                write!(
+                    f,
+                    "Workspace members {first} and {second} take the dependency {name} from different sources, all members have to use the same source"
+                )
+            }
+            LingoError::ConflictingWorkspaceFeature(name, first, second) => {
+                write!(
+                    f,
+                    "Workspace members {first} and {second} define the feature {name} differently, features are shared by the whole workspace"
+                )
+            }
+            LingoError::ConflictingDependency(name) => {
+                write!(
+                    f,
+                    "The dependency {name} is declared with different sources, the package and its apps have to use the same source"
+                )
+            }
         }
     }
 }
@@ -0,0 +1,517 @@
+use std::path::Path;
+
+use git2::BranchType::{Local, Remote};
+use git2::{
+    BranchType, Commit, Direction, FetchOptions, Object, ObjectType, Reference, Repository,
+    TreeWalkMode, TreeWalkResult,
+};
+
+use crate::package::cache::PackageCache;
+use crate::package::tree::GitLock;
+use crate::{GitCloneError, GitCloneOptions, GitUrl};
+
+fn get_branch<'a>(
+    repo: &'a git2::Repository,
+    branch: &str,
+    branch_type: BranchType,
+) -> Result<(Object<'a>, Option<Reference<'a>>), GitCloneError> {
+    let reference = repo
+        .find_branch(branch, branch_type)
+        .map_err(|_| GitCloneError("cannot find branch".to_string()))?
+        .into_reference();
+    Ok((
+        reference
+            .peel(ObjectType::Any)
+            .map_err(|_| GitCloneError("cannot peel object".to_string()))?,
+        Some(reference),
+    ))
+}
+
+/// Fetches the refspecs into the mirror, only the newest commit if the remote supports it
+fn fetch_into_mirror(
+    mirror: &Repository,
+    url: &str,
+    refspecs: &[String],
+) -> Result<(), git2::Error> {
+    let mut shallow = FetchOptions::new();
+    shallow.depth(1);
+
+    // the local transport and some servers can't do shallow fetches
+    let shallow = mirror
+        .remote_anonymous(url)?
+        .fetch(refspecs, Some(&mut shallow), None);
+
+    match shallow {
+        Ok(()) => Ok(()),
+        Err(shallow) => mirror
+            .remote_anonymous(url)?
+            .fetch(refspecs, None, None)
+            .map_err(|e| retry_error(&shallow, e)),
+    }
+}
+
+/// keeps the reason why the first attempt failed as well
+fn retry_error(first: &git2::Error, retry: git2::Error) -> git2::Error {
+    git2::Error::from_str(&format!("{first}, retrying failed: {retry}"))
+}
+
+/// commit the git lock refers to, locked revisions that are already mirrored aren't fetched again
+fn mirror_commit<'a>(
+    mirror: &'a Repository,
+    url: &str,
+    git_tag: &Option<GitLock>,
+) -> Result<Commit<'a>, GitCloneError> {
+    let fetch_error = |e: git2::Error| GitCloneError(format!("cannot fetch {url}: {e}"));
+    let find = |spec: &str| {
+        mirror
+            .revparse_single(spec)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| GitCloneError(format!("cannot find {spec} in {url}: {e}")))
+    };
+
+    let (refspec, reference) = match git_tag {
+        Some(GitLock::Rev(rev)) => {
+            if let Ok(commit) = find(rev) {
+                return Ok(commit);
+            }
+
+            // not every server hands out single commits, then all branches and tags are fetched
+            fetch_into_mirror(mirror, url, &[format!("+{rev}:refs/lingo/fetched")])
+                .or_else(|first| {
+                    fetch_into_mirror(
+                        mirror,
+                        url,
+                        &[
+                            "+refs/heads/*:refs/heads/*".to_string(),
+                            "+refs/tags/*:refs/tags/*".to_string(),
+                        ],
+                    )
+                    .map_err(|e| retry_error(&first, e))
+                })
+                .map_err(fetch_error)?;
+            return find(rev);
+        }
+        Some(GitLock::Tag(tag)) => (
+            format!("+refs/tags/{tag}:refs/tags/{tag}"),
+            format!("refs/tags/{tag}"),
+        ),
+        Some(GitLock::Branch(branch)) => {
+            let branch = branch.strip_prefix("origin/").unwrap_or(branch);
+            (
+                format!("+refs/heads/{branch}:refs/heads/{branch}"),
+                format!("refs/heads/{branch}"),
+            )
+        }
+        None => (
+            "+HEAD:refs/lingo/HEAD".to_string(),
+            "refs/lingo/HEAD".to_string(),
+        ),
+    };
+
+    fetch_into_mirror(mirror, url, &[refspec]).map_err(fetch_error)?;
+    find(&reference)
+}
+
+/// writes all files of the commit into `outpath`, submodules become empty directories
+fn write_tree(repo: &Repository, commit: &Commit, outpath: &Path) -> Result<(), anyhow::Error> {
+    std::fs::create_dir_all(outpath)?;
+    let tree = commit.tree()?;
+
+    let mut result = Ok(());
+    tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+        let path = outpath.join(parent).join(entry.name().unwrap_or_default());
+        let written = match entry.kind() {
+            Some(ObjectType::Tree) | Some(ObjectType::Commit) => {
+                std::fs::create_dir_all(&path).map_err(anyhow::Error::from)
+            }
+            Some(ObjectType::Blob) => repo
+                .find_blob(entry.id())
+                .map_err(anyhow::Error::from)
+                .and_then(|blob| write_blob(&path, blob.content(), entry.filemode())),
+            _ => Ok(()),
+        };
+
+        match written {
+            Ok(()) => TreeWalkResult::Ok,
+            Err(e) => {
+                result = Err(e);
+                TreeWalkResult::Abort
+            }
+        }
+    })?;
+
+    result
+}
+
+#[cfg(unix)]
+fn write_blob(path: &Path, content: &[u8], filemode: i32) -> Result<(), anyhow::Error> {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    match filemode {
+        0o120000 => symlink(String::from_utf8_lossy(content).as_ref(), path)?,
+        _ => {
+            std::fs::write(path, content)?;
+            if filemode == 0o100755 {
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn write_blob(path: &Path, content: &[u8], _filemode: i32) -> Result<(), anyhow::Error> {
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Checks out the files of the commit from a bare mirror in the package cache, `outpath` only
+/// receives the files and no repository.
+fn checkout_from_mirror(
+    cache: &PackageCache,
+    url: &str,
+    outpath: &Path,
+    git_tag: &Option<GitLock>,
+    submodules: bool,
+) -> Result<String, GitCloneError> {
+    let path = cache.git_mirror(url);
+
+    // other lingo processes may use the same mirror, the lock is released before the
+    // submodules are checked out because they take it again
+    let lock = cache
+        .lock(true)
+        .map_err(|e| GitCloneError(format!("cannot lock the package cache {e}")))?;
+    let mirror = if path.exists() {
+        Repository::open_bare(&path)
+    } else {
+        Repository::init_bare(&path)
+    }
+    .map_err(|e| GitCloneError(format!("cannot open mirror {}: {e}", path.display())))?;
+
+    let commit = mirror_commit(&mirror, url, git_tag)?;
+
+    write_tree(&mirror, &commit, outpath)
+        .map_err(|e| GitCloneError(format!("cannot checkout rev {e}")))?;
+    drop(lock);
+
+    if submodules {
+        checkout_submodules(cache, url, &commit, outpath)?;
+    }
+
+    Ok(commit.id().to_string())
+}
+
+/// checks out every submodule at the commit the tree records for it
+fn checkout_submodules(
+    cache: &PackageCache,
+    url: &str,
+    commit: &Commit,
+    outpath: &Path,
+) -> Result<(), GitCloneError> {
+    let gitmodules = outpath.join(".gitmodules");
+    if !gitmodules.exists() {
+        return Ok(());
+    }
+
+    let config_error = |e: git2::Error| GitCloneError(format!("cannot read .gitmodules {e}"));
+    let config = git2::Config::open(&gitmodules).map_err(config_error)?;
+    let mut paths = vec![];
+    let mut entries = config
+        .entries(Some(r"submodule\..*\.path"))
+        .map_err(config_error)?;
+    while let Some(entry) = entries.next() {
+        let entry = entry.map_err(config_error)?;
+        if let (Some(key), Some(path)) = (entry.name(), entry.value()) {
+            paths.push((key.trim_end_matches(".path").to_string(), path.to_string()));
+        }
+    }
+
+    let tree = commit
+        .tree()
+        .map_err(|e| GitCloneError(format!("cannot read tree {e}")))?;
+    for (key, path) in paths {
+        // submodules that are declared but not committed have nothing to check out
+        let Ok(gitlink) = tree.get_path(Path::new(&path)) else {
+            continue;
+        };
+        if gitlink.kind() != Some(ObjectType::Commit) {
+            continue;
+        }
+
+        let submodule_url = config
+            .get_string(&format!("{key}.url"))
+            .map_err(config_error)?;
+
+        // relative urls are relative to the repository of the parent
+        let submodule_url = if submodule_url.starts_with("./") || submodule_url.starts_with("../") {
+            url::Url::parse(&format!("{}/", url.trim_end_matches('/')))
+                .and_then(|base| base.join(&submodule_url))
+                .map_err(|e| GitCloneError(format!("cannot resolve {submodule_url}: {e}")))?
+                .to_string()
+        } else {
+            submodule_url
+        };
+
+        checkout_from_mirror(
+            cache,
+            &submodule_url,
+            &outpath.join(&path),
+            &Some(GitLock::Rev(gitlink.id().to_string())),
+            true,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// clones the repository or, if only the files are needed, checks them out from a mirror
+pub fn clone_and_checkout(
+    git_url: GitUrl,
+    outpath: &Path,
+    git_tag: Option<GitLock>,
+    options: GitCloneOptions,
+) -> Result<Option<String>, GitCloneError> {
+    let url = <&str>::from(git_url);
+
+    // without a cache the repository is cloned completely
+    if let (true, Some(cache)) = (options.files_only, PackageCache::open()) {
+        return checkout_from_mirror(&cache, url, outpath, &git_tag, options.submodules).map(Some);
+    }
+
+    let repo = if options.submodules {
+        Repository::clone_recurse(url, outpath)
+    } else {
+        Repository::clone(url, outpath)
+    }
+    .map_err(|_| GitCloneError("clone failed".to_string()))?;
+    let mut git_rev = None;
+
+    if let Some(git_lock) = git_tag {
+        let (object, reference) = match git_lock {
+            GitLock::Tag(tag) => repo
+                .revparse_ext(&tag)
+                .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
+            GitLock::Branch(branch) => {
+                if let Ok(val) = get_branch(&repo, &branch, Local) {
+                    val
+                } else {
+                    get_branch(&repo, &branch, Remote)?
+                }
+            }
+            GitLock::Rev(rev) => repo
+                .revparse_ext(&rev)
+                .map_err(|e| GitCloneError(format!("cannot parse rev {e}")))?,
+        };
+        repo.checkout_tree(&object, None)
+            .map_err(|e| GitCloneError(format!("cannot checkout rev {e}")))?;
+
+        // TODO: this produces hard to debug output
+
+        match reference {
+            // gref is an actual reference like branches or tags
+            Some(gref) => {
+                git_rev = gref.target().map(|v| v.to_string());
+                repo.set_head(gref.name().unwrap())
+            }
+            // this is a commit, not a reference
+            None => repo.set_head_detached(object.id()),
+        }
+        .map_err(|_| GitCloneError("cannot checkout rev".to_string()))?;
+    }
+
+    Ok(git_rev)
+}
+
+/// lists the tags of the remote repository without cloning it
+pub fn list_tags(git_url: GitUrl) -> Result<Vec<String>, GitCloneError> {
+    let mut remote = git2::Remote::create_detached(<&str>::from(git_url))
+        .map_err(|e| GitCloneError(format!("invalid remote {e}")))?;
+    remote
+        .connect(Direction::Fetch)
+        .map_err(|e| GitCloneError(format!("cannot connect to remote {e}")))?;
+
+    let heads = remote
+        .list()
+        .map_err(|e| GitCloneError(format!("cannot list references {e}")))?;
+
+    // annotated tags are listed twice, the peeled entries end with ^{}
+    Ok(heads
+        .iter()
+        .filter_map(|head| head.name().strip_prefix("refs/tags/"))
+        .filter(|tag| !tag.ends_with("^{}"))
+        .map(String::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Index, IndexEntry, IndexTime, Oid, Signature};
+    use std::fs;
+    use tempfile::tempdir;
+    use url::Url;
+
+    /// commits the files and submodule links onto `main` of the bare repository
+    fn commit(repo: &Repository, files: &[(&str, &str, u32)], gitlinks: &[(&str, Oid)]) -> Oid {
+        let mut index = Index::new().unwrap();
+        let blobs = files
+            .iter()
+            .map(|(path, content, mode)| (path, *mode, repo.blob(content.as_bytes()).unwrap()));
+        let links = gitlinks.iter().map(|(path, id)| (path, 0o160000, *id));
+        for (path, mode, id) in blobs.chain(links) {
+            index
+                .add(&IndexEntry {
+                    ctime: IndexTime::new(0, 0),
+                    mtime: IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode,
+                    uid: 0,
+                    gid: 0,
+                    file_size: 0,
+                    id,
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.as_bytes().to_vec(),
+                })
+                .unwrap();
+        }
+
+        let tree = repo.find_tree(index.write_tree_to(repo).unwrap()).unwrap();
+        let signature = Signature::now("lingo", "lingo@example.org").unwrap();
+        let parent = repo
+            .find_reference("refs/heads/main")
+            .and_then(|reference| reference.peel_to_commit())
+            .ok();
+        let id = repo
+            .commit(
+                Some("refs/heads/main"),
+                &signature,
+                &signature,
+                "commit",
+                &tree,
+                &parent.iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        id
+    }
+
+    fn bare_repository(path: &Path) -> (Repository, String) {
+        let repo = Repository::init_bare(path).unwrap();
+        let url = Url::from_file_path(path).unwrap().to_string();
+        (repo, url)
+    }
+
+    fn read(root: &Path, path: &str) -> String {
+        fs::read_to_string(root.join(path)).unwrap()
+    }
+
+    #[test]
+    fn checks_out_tags_branches_and_revisions_from_the_mirror() {
+        let dir = tempdir().unwrap();
+        let (repo, url) = bare_repository(&dir.path().join("lib.git"));
+        let first = commit(
+            &repo,
+            &[
+                ("Lingo.toml", "first", 0o100644),
+                ("scripts/build.sh", "#!/bin/sh", 0o100755),
+            ],
+            &[],
+        );
+        let signature = Signature::now("lingo", "lingo@example.org").unwrap();
+        repo.tag(
+            "v1",
+            &repo.find_object(first, None).unwrap(),
+            &signature,
+            "v1",
+            false,
+        )
+        .unwrap();
+        let second = commit(&repo, &[("Lingo.toml", "second", 0o100644)], &[]);
+
+        let cache = PackageCache::at(dir.path().join("cache"));
+        let checkout = |git_tag: Option<GitLock>| {
+            let outpath = tempdir().unwrap();
+            let rev = checkout_from_mirror(&cache, &url, outpath.path(), &git_tag, false).unwrap();
+            (outpath, rev)
+        };
+
+        let (outpath, rev) = checkout(None);
+        assert_eq!(rev, second.to_string());
+        assert_eq!(read(outpath.path(), "Lingo.toml"), "second");
+        assert!(!outpath.path().join("scripts").exists());
+        assert!(!outpath.path().join(".git").exists());
+
+        let (outpath, rev) = checkout(Some(GitLock::Tag("v1".to_string())));
+        assert_eq!(rev, first.to_string());
+        assert_eq!(read(outpath.path(), "Lingo.toml"), "first");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(outpath.path().join("scripts/build.sh"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+
+        let (outpath, _) = checkout(Some(GitLock::Branch("origin/main".to_string())));
+        assert_eq!(read(outpath.path(), "Lingo.toml"), "second");
+
+        // locked revisions are taken from the mirror
+        let (outpath, rev) = checkout(Some(GitLock::Rev(first.to_string())));
+        assert_eq!(rev, first.to_string());
+        assert_eq!(read(outpath.path(), "Lingo.toml"), "first");
+        assert!(cache.git_mirror(&url).exists());
+
+        assert_eq!(list_tags(GitUrl::from(url.as_str())).unwrap(), vec!["v1"]);
+    }
+
+    #[test]
+    fn checks_out_submodules_with_relative_urls() {
+        let dir = tempdir().unwrap();
+        let (sub, _) = bare_repository(&dir.path().join("sub.git"));
+        let sub_commit = commit(&sub, &[("Sub.lf", "reactor Sub {}", 0o100644)], &[]);
+
+        let (repo, url) = bare_repository(&dir.path().join("lib.git"));
+        commit(
+            &repo,
+            &[(
+                ".gitmodules",
+                "[submodule \"sub\"]\n\tpath = deps/sub\n\turl = ../sub.git\n",
+                0o100644,
+            )],
+            &[("deps/sub", sub_commit)],
+        );
+
+        let cache = PackageCache::at(dir.path().join("cache"));
+        let outpath = tempdir().unwrap();
+        checkout_from_mirror(&cache, &url, outpath.path(), &None, true).unwrap();
+        assert_eq!(read(outpath.path(), "deps/sub/Sub.lf"), "reactor Sub {}");
+
+        // without submodules the directory stays empty
+        let outpath = tempdir().unwrap();
+        checkout_from_mirror(&cache, &url, outpath.path(), &None, false).unwrap();
+        assert!(outpath
+            .path()
+            .join("deps/sub")
+            .read_dir()
+            .unwrap()
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn fetch_errors_are_reported() {
+        let dir = tempdir().unwrap();
+        let url = Url::from_file_path(dir.path().join("missing.git"))
+            .unwrap()
+            .to_string();
+        let cache = PackageCache::at(dir.path().join("cache"));
+
+        let GitCloneError(message) =
+            checkout_from_mirror(&cache, &url, &dir.path().join("out"), &None, false).unwrap_err();
+        assert!(message.contains("retrying failed"), "{message}");
+    }
+}
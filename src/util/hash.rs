@@ -0,0 +1,216 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+/// prefix of checksums computed with sha256
+pub const SHA256_PREFIX: &str = "sha256:";
+
+/// directory names that are skipped wherever they appear
+const IGNORED_DIRECTORIES: &[&str] = &[".git"];
+
+/// directories that are skipped if they are located directly inside the hashed directory
+const IGNORED_ROOT_DIRECTORIES: &[&str] = &["build"];
+
+/// Algorithms checksums can be computed with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Bare hex encoded sha1, written by older versions of lingo. Their directory checksums
+    /// depended on the working directory and can't be recomputed, only file checksums can.
+    Sha1,
+    /// hex encoded sha256 prefixed with `sha256:`
+    Sha256,
+}
+
+impl Algorithm {
+    /// algorithm the given checksum was computed with
+    pub fn of(checksum: &str) -> Algorithm {
+        if checksum.starts_with(SHA256_PREFIX) {
+            Algorithm::Sha256
+        } else {
+            Algorithm::Sha1
+        }
+    }
+}
+
+/// checksum without the algorithm prefix, used as directory name
+pub fn hex(checksum: &str) -> &str {
+    checksum.strip_prefix(SHA256_PREFIX).unwrap_or(checksum)
+}
+
+/// Decides which entries of a directory are not part of its checksum
+pub struct Ignore {
+    globs: GlobSet,
+}
+
+impl Ignore {
+    pub fn new(patterns: &[String]) -> io::Result<Ignore> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob =
+                Glob::new(pattern).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+            builder.add(glob);
+        }
+
+        Ok(Ignore {
+            globs: builder
+                .build()
+                .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?,
+        })
+    }
+
+    /// `path` is relative to the hashed directory
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let name = path.file_name().and_then(|name| name.to_str());
+        let top_level = path.components().count() == 1;
+
+        if let (true, Some(name)) = (is_dir, name) {
+            if IGNORED_DIRECTORIES.contains(&name)
+                || (top_level && IGNORED_ROOT_DIRECTORIES.contains(&name))
+            {
+                return true;
+            }
+        }
+
+        self.globs.is_match(path)
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn read_into<D: Digest>(path: &Path, digest: &mut D) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        digest.update(&buffer[..read]);
+    }
+}
+
+/// checksum over the raw bytes of a file
+pub fn file_checksum(path: &Path, algorithm: Algorithm) -> io::Result<String> {
+    match algorithm {
+        Algorithm::Sha1 => {
+            let mut sha = Sha1::new();
+            read_into(path, &mut sha)?;
+            Ok(encode(&sha.finalize()))
+        }
+        Algorithm::Sha256 => {
+            let mut sha = Sha256::new();
+            read_into(path, &mut sha)?;
+            Ok(format!("{SHA256_PREFIX}{}", encode(&sha.finalize())))
+        }
+    }
+}
+
+/// Sha256 checksum over the content of the directory `root`, entries that are ignored don't
+/// contribute to it. The checksum only depends on the names and contents below `root`.
+pub fn dir_checksum(root: &Path, ignore: &[String]) -> io::Result<String> {
+    let ignore = Ignore::new(ignore)?;
+    let hash = tree_hash(root, Path::new(""), &ignore)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", root.display(), e)))?;
+    Ok(format!("{SHA256_PREFIX}{}", encode(&hash)))
+}
+
+/// Merkle tree hash: every directory hashes the sorted names of its children together with
+/// their hashes, so a change anywhere below changes all hashes up to the root.
+fn tree_hash(root: &Path, relative: &Path, ignore: &Ignore) -> io::Result<Vec<u8>> {
+    let path = root.join(relative);
+    let file_type = path.symlink_metadata()?.file_type();
+
+    let mut sha = Sha256::new();
+
+    if file_type.is_file() {
+        sha.update(b"f");
+        read_into(&path, &mut sha)?;
+    } else if file_type.is_symlink() {
+        sha.update(b"l");
+        sha.update(fs::read_link(&path)?.as_os_str().as_encoded_bytes());
+    } else if file_type.is_dir() {
+        sha.update(b"d");
+
+        let mut children = Vec::new();
+        for child in path.read_dir()? {
+            let child = child?;
+            let child_path = relative.join(child.file_name());
+            if !ignore.is_ignored(&child_path, child.file_type()?.is_dir()) {
+                children.push((child.file_name(), child_path));
+            }
+        }
+        children.sort();
+
+        for (name, child_path) in children {
+            let name = name.as_encoded_bytes();
+            sha.update((name.len() as u32).to_le_bytes());
+            sha.update(name);
+            sha.update(tree_hash(root, &child_path, ignore)?);
+        }
+    } else {
+        return Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("unsupported file type {}", relative.display()),
+        ));
+    }
+
+    Ok(sha.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn tree_hash_covers_names_and_structure() {
+        let root = tempdir().unwrap();
+        write(root.path(), "src/lib/Lib.lf", "reactor Lib {}");
+        let checksum = dir_checksum(root.path(), &[]).unwrap();
+        assert!(checksum.starts_with(SHA256_PREFIX));
+
+        let mut seen = vec![checksum];
+        let mut assert_changed = |root: &Path| {
+            let checksum = dir_checksum(root, &[]).unwrap();
+            assert!(!seen.contains(&checksum), "{checksum} was seen before");
+            seen.push(checksum);
+        };
+
+        // renaming a file with the same content
+        fs::rename(
+            root.path().join("src/lib/Lib.lf"),
+            root.path().join("src/lib/Main.lf"),
+        )
+        .unwrap();
+        assert_changed(root.path());
+
+        // moving it into another directory
+        fs::rename(
+            root.path().join("src/lib/Main.lf"),
+            root.path().join("src/Main.lf"),
+        )
+        .unwrap();
+        assert_changed(root.path());
+
+        // empty directories are part of the tree
+        fs::remove_dir(root.path().join("src/lib")).unwrap();
+        assert_changed(root.path());
+    }
+
+    #[test]
+    fn detects_legacy_checksums() {
+        assert_eq!(
+            Algorithm::of("111d0d841fed9bdd83a6b70bf2ada7e3ccbf0c83"),
+            Algorithm::Sha1
+        );
+        assert_eq!(Algorithm::of("sha256:00ff"), Algorithm::Sha256);
+        assert_eq!(hex("sha256:00ff"), "00ff");
+    }
+}
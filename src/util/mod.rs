@@ -1,7 +1,12 @@
 pub mod analyzer;
+pub mod archive;
 mod command_line;
 pub mod errors;
-pub mod sha1dir;
+#[cfg(feature = "binary")]
+pub mod git;
+pub mod hash;
+#[cfg(test)]
+pub(crate) mod testing;
 
 pub use command_line::*;
 use std::path::{Path, PathBuf};
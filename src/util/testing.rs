@@ -0,0 +1,16 @@
+//! Helpers shared by the unit tests
+
+use std::fs;
+use std::path::Path;
+
+/// writes `content` to `path` inside `root` and creates the missing directories
+pub(crate) fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// Lingo.toml of a C++ library, dependencies can be appended to it
+pub(crate) fn library_toml(name: &str, version: &str) -> String {
+    format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n\n[lib]\ntarget = \"Cpp\"\n\n[lib.properties]\n\n[dependencies]\n")
+}
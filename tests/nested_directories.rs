@@ -0,0 +1,303 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use tempfile::tempdir;
+
+fn write(root: &Path, path: &str, content: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn library(name: &str) -> String {
+    format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n\n[lib]\ntarget = \"Cpp\"\n\n[lib.properties]\n\n[dependencies]\n")
+}
+
+fn app(name: &str, dependencies: &str) -> String {
+    format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n\n[[app]]\nname = \"{name}\"\ntarget = \"Cpp\"\nmain = \"src/Main.lf\"\n\n[app.properties]\n\n[dependencies]\n{dependencies}")
+}
+
+/// runs lingo inside `directory` with a package cache that is private to the test
+fn run_lingo(directory: &Path, cache: &Path, args: &[&str]) -> ExitStatus {
+    Command::new(env!("CARGO_BIN_EXE_lingo"))
+        .args(args)
+        .current_dir(directory)
+        .env("LINGO_CACHE", cache)
+        .env_remove("LINGO_REGISTRY")
+        .env_remove("LINGO_OFFLINE")
+        .status()
+        .unwrap()
+}
+
+fn lingo(directory: &Path, cache: &Path, args: &[&str]) {
+    assert!(run_lingo(directory, cache, args).success());
+}
+
+/// creates a bare repository with a single commit that carries the tags
+fn tagged_repository(path: &Path, tags: &[&str]) -> String {
+    let repo = git2::Repository::init_bare(path).unwrap();
+    let blob = repo.blob(library("remote").as_bytes()).unwrap();
+    let mut tree = repo.treebuilder(None).unwrap();
+    tree.insert("Lingo.toml", blob, 0o100644).unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let signature = git2::Signature::now("lingo", "lingo@example.org").unwrap();
+    let commit = repo
+        .commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+        .unwrap();
+
+    let commit = repo.find_object(commit, None).unwrap();
+    for tag in tags {
+        repo.tag_lightweight(tag, &commit, false).unwrap();
+    }
+    url::Url::from_file_path(path).unwrap().to_string()
+}
+
+#[test]
+fn package_paths_start_at_the_package_root() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let project = dir.path().join("project");
+    write(
+        &project,
+        "Lingo.toml",
+        &app(
+            "plant",
+            "motors = { version = \">=0.1\", path = \"libs/motors\" }\n",
+        ),
+    );
+    write(&project, "src/Main.lf", "main reactor {}");
+    write(&project, "libs/motors/Lingo.toml", &library("motors"));
+    write(&project, "libs/motors/src/lib/Motor.lf", "reactor Motor {}");
+
+    let nested = project.join("src");
+    lingo(&nested, &cache, &["update"]);
+    assert!(project.join("Lingo.lock").exists());
+    assert!(project.join("build/lfc_include/motors/Lingo.toml").exists());
+    assert!(!nested.join("Lingo.lock").exists());
+    assert!(!nested.join("build").exists());
+    assert!(!nested.join("motors").exists());
+
+    // the locked packages are restored into the output directory of the package root
+    fs::remove_dir_all(project.join("build")).unwrap();
+    lingo(&nested, &cache, &["build", "--no-compile", "--locked"]);
+    assert!(project.join("build/lfc_include/motors/Lingo.toml").exists());
+    assert!(!nested.join("build").exists());
+
+    lingo(&nested, &cache, &["clean"]);
+    assert!(!project.join("build").exists());
+    assert!(!project.join("Lingo.lock").exists());
+}
+
+#[test]
+fn path_dependencies_are_relative_to_the_declaring_package() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let project = dir.path().join("app");
+    write(
+        &project,
+        "Lingo.toml",
+        &app(
+            "app",
+            "lib = { version = \">=0.1\", path = \"../libs/lib\" }\n",
+        ),
+    );
+    write(&project, "src/Main.lf", "main reactor {}");
+    write(
+        dir.path(),
+        "libs/lib/Lingo.toml",
+        &(library("lib") + "util = { version = \">=0.1\", path = \"../util\" }\n"),
+    );
+    write(dir.path(), "libs/lib/src/lib/Lib.lf", "reactor Lib {}");
+    write(dir.path(), "libs/util/Lingo.toml", &library("util"));
+    write(dir.path(), "libs/util/src/lib/Util.lf", "reactor Util {}");
+
+    lingo(&project, &cache, &["update"]);
+    assert!(project.join("build/lfc_include/util/Lingo.toml").exists());
+    let lock = fs::read_to_string(project.join("Lingo.lock")).unwrap();
+    assert!(lock.contains("path+../libs/util"), "{lock}");
+
+    // the lock keeps pointing at the right directory
+    fs::remove_dir_all(project.join("build")).unwrap();
+    lingo(&project, &cache, &["build", "--no-compile", "--locked"]);
+    assert!(project.join("build/lfc_include/util/Lingo.toml").exists());
+}
+
+#[test]
+fn outdated_looks_up_sources_from_nested_directories() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let project = dir.path().join("project");
+    let remote = tagged_repository(
+        &dir.path().join("remote.git"),
+        &["v0.1.0", "v0.1.2", "v0.3.0"],
+    );
+    write(
+        &project,
+        "Lingo.toml",
+        &app(
+            "plant",
+            &format!(
+                "motors = {{ version = \">=0.1\", path = \"libs/motors\" }}\nremote = {{ version = \"^0.1.0\", git = \"{remote}\", tag = \"v0.1.0\" }}\n"
+            ),
+        ),
+    );
+    write(&project, "src/Main.lf", "main reactor {}");
+    write(&project, "libs/motors/Lingo.toml", &library("motors"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lingo"))
+        .arg("outdated")
+        .current_dir(project.join("src"))
+        .env("LINGO_CACHE", &cache)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let table = String::from_utf8(output.stdout).unwrap();
+    let row = |name: &str| {
+        table
+            .lines()
+            .find(|line| line.starts_with(name))
+            .unwrap_or_else(|| panic!("{table}"))
+            .split_whitespace()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(row("motors"), ["motors", ">=0.1", "-", "0.1.0", "0.1.0"]);
+    assert_eq!(row("remote"), ["remote", "^0.1.0", "-", "0.1.2", "0.3.0"]);
+}
+
+#[test]
+fn builds_keep_dev_dependencies_locked_without_fetching_them() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let project = dir.path().join("project");
+    let repository = dir.path().join("remote.git");
+    let remote = tagged_repository(&repository, &["v0.1.0"]);
+    let dev_dependencies = format!(
+        "\n[dev-dependencies]\nremote = {{ version = \"^0.1.0\", git = \"{remote}\", tag = \"v0.1.0\" }}\n"
+    );
+    write(
+        &project,
+        "Lingo.toml",
+        &(app(
+            "plant",
+            "motors = { version = \">=0.1\", path = \"libs/motors\" }\n",
+        ) + &dev_dependencies),
+    );
+    write(&project, "src/Main.lf", "main reactor {}");
+    write(&project, "libs/motors/Lingo.toml", &library("motors"));
+    write(&project, "libs/sensors/Lingo.toml", &library("sensors"));
+
+    lingo(&project, &cache, &["update"]);
+    let lock = fs::read_to_string(project.join("Lingo.lock")).unwrap();
+    assert!(lock.contains("name = \"remote\""), "{lock}");
+
+    // a new dependency re-resolves the lock, the dev-dependency can't be fetched anymore
+    fs::remove_dir_all(&repository).unwrap();
+    fs::remove_dir_all(project.join("build")).unwrap();
+    write(
+        &project,
+        "Lingo.toml",
+        &(app(
+            "plant",
+            "motors = { version = \">=0.1\", path = \"libs/motors\" }\nsensors = { version = \">=0.1\", path = \"libs/sensors\" }\n",
+        ) + &dev_dependencies),
+    );
+    lingo(&project, &cache, &["build", "--no-compile"]);
+
+    let lock = fs::read_to_string(project.join("Lingo.lock")).unwrap();
+    assert!(lock.contains("name = \"remote\""), "{lock}");
+    assert!(lock.contains("name = \"sensors\""), "{lock}");
+    assert!(project
+        .join("build/lfc_include/sensors/Lingo.toml")
+        .exists());
+    assert!(!project.join("build/lfc_include/remote").exists());
+}
+
+#[test]
+fn workspace_members_share_the_lock_of_the_root() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let workspace = dir.path().join("workspace");
+    write(
+        &workspace,
+        "Lingo.toml",
+        "[workspace]\nmembers = [\"sim/*\", \"libs/*\"]\n",
+    );
+    write(&workspace, "libs/motors/Lingo.toml", &library("motors"));
+    write(
+        &workspace,
+        "libs/motors/src/lib/Motor.lf",
+        "reactor Motor {}",
+    );
+    write(
+        &workspace,
+        "sim/plant/Lingo.toml",
+        &app(
+            "plant",
+            "motors = { version = \">=0.1\", path = \"../../libs/motors\" }\n",
+        ),
+    );
+    write(&workspace, "sim/plant/src/Main.lf", "main reactor {}");
+
+    let member = workspace.join("sim/plant");
+    lingo(&member.join("src"), &cache, &["update"]);
+    assert!(workspace.join("Lingo.lock").exists());
+    assert!(workspace
+        .join("build/lfc_include/motors/Lingo.toml")
+        .exists());
+    assert!(!member.join("Lingo.lock").exists());
+    assert!(!member.join("build").exists());
+
+    fs::remove_dir_all(workspace.join("build")).unwrap();
+    lingo(
+        &member,
+        &cache,
+        &["build", "--no-compile", "--locked", "-p", "plant"],
+    );
+    assert!(workspace
+        .join("build/lfc_include/motors/Lingo.toml")
+        .exists());
+}
+
+#[test]
+fn locked_builds_fail_without_a_lock() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let project = dir.path().join("project");
+    write(
+        &project,
+        "Lingo.toml",
+        &app(
+            "plant",
+            "motors = { version = \">=0.1\", path = \"libs/motors\" }\n",
+        ),
+    );
+    write(&project, "src/Main.lf", "main reactor {}");
+    write(&project, "libs/motors/Lingo.toml", &library("motors"));
+
+    let status = run_lingo(&project, &cache, &["build", "--no-compile", "--locked"]);
+    assert_eq!(status.code(), Some(1));
+    assert!(!project.join("Lingo.lock").exists());
+}
+
+#[test]
+fn failed_add_restores_the_lingo_toml() {
+    let dir = tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let project = dir.path().join("project");
+    let manifest = app("plant", "");
+    write(&project, "Lingo.toml", &manifest);
+    write(&project, "src/Main.lf", "main reactor {}");
+
+    let status = run_lingo(
+        &project.join("src"),
+        &cache,
+        &["add", "foo", "--version", "^1.2", "--path", "../foo"],
+    );
+    assert_eq!(status.code(), Some(1));
+    assert_eq!(
+        fs::read_to_string(project.join("Lingo.toml")).unwrap(),
+        manifest
+    );
+}